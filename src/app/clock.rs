@@ -0,0 +1,114 @@
+use std::cell::Cell;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use egui_sdl2_platform::sdl2;
+
+/// Source of time for the main loop, the frame statistics and the frame pacing, counted in ticks
+/// of a fixed frequency.
+pub(super) trait Clock {
+    /// Returns the current counter value in ticks.
+    fn now(&self) -> u64;
+
+    /// Returns the number of ticks per second.
+    fn frequency(&self) -> u64;
+
+    /// Waits for `duration`.
+    fn sleep(&self, duration: Duration) {
+        thread::sleep(duration);
+    }
+
+    /// Waits until the counter reaches `ticks`, without giving up the thread.
+    fn spin_until(&self, ticks: u64) {
+        while self.now() < ticks {
+            std::hint::spin_loop();
+        }
+    }
+
+    /// Converts a number of ticks to seconds.
+    #[allow(clippy::as_conversions, clippy::cast_precision_loss)]
+    fn to_seconds(&self, ticks: f64) -> f64 {
+        ticks / self.frequency() as f64
+    }
+
+    /// Returns the current time in seconds.
+    #[allow(clippy::as_conversions, clippy::cast_precision_loss)]
+    fn now_s(&self) -> f64 {
+        self.to_seconds(self.now() as f64)
+    }
+}
+
+/// Clock reading the high resolution performance counter of SDL.
+pub(super) struct SdlClock;
+
+impl Clock for SdlClock {
+    #[allow(unsafe_code)]
+    fn now(&self) -> u64 {
+        unsafe { sdl2::sys::SDL_GetPerformanceCounter() }
+    }
+
+    #[allow(unsafe_code)]
+    fn frequency(&self) -> u64 {
+        unsafe { sdl2::sys::SDL_GetPerformanceFrequency() }
+    }
+}
+
+/// Clock counting nanoseconds with the monotonic clock of the standard library, for use without
+/// SDL.
+pub(super) struct StdClock {
+    start: Instant,
+}
+
+impl StdClock {
+    pub(super) fn new() -> Self {
+        Self { start: Instant::now() }
+    }
+}
+
+impl Clock for StdClock {
+    fn now(&self) -> u64 {
+        u64::try_from(self.start.elapsed().as_nanos()).unwrap_or(u64::MAX)
+    }
+
+    fn frequency(&self) -> u64 {
+        1_000_000_000
+    }
+}
+
+/// Virtual clock which only advances when told to, or when waited on, so that runs are
+/// deterministic and independent of the actual time spent on each frame.
+pub(super) struct ManualClock {
+    ticks:     Cell<u64>,
+    frequency: u64,
+}
+
+impl ManualClock {
+    pub(super) fn new(frequency: u64) -> Self {
+        Self { ticks: Cell::new(0), frequency: frequency.max(1) }
+    }
+
+    /// Advances the clock by `ticks`.
+    #[cfg(test)]
+    pub(super) fn advance(&self, ticks: u64) {
+        self.ticks.set(self.ticks.get().saturating_add(ticks));
+    }
+}
+
+impl Clock for ManualClock {
+    fn now(&self) -> u64 {
+        self.ticks.get()
+    }
+
+    fn frequency(&self) -> u64 {
+        self.frequency
+    }
+
+    fn sleep(&self, duration: Duration) {
+        let ticks = duration.as_nanos().saturating_mul(u128::from(self.frequency)) / 1_000_000_000;
+        self.ticks.set(self.ticks.get().saturating_add(u64::try_from(ticks).unwrap_or(u64::MAX)));
+    }
+
+    fn spin_until(&self, ticks: u64) {
+        self.ticks.set(self.ticks.get().max(ticks));
+    }
+}
@@ -4,4 +4,30 @@ pub(crate) const WIDTH: u32 = 1920;
 /// The height of the application window.
 pub(crate) const HEIGHT: u32 = 1080;
 
-pub(crate) const TARGET_FPS: i32 = 60;
+/// The default internal render resolution, relative to the window size.
+pub(crate) const RENDER_SCALE: f32 = 1.0;
+
+/// The lowest render scale the dynamic resolution controller may select.
+pub(crate) const MIN_DYNAMIC_RENDER_SCALE: f32 = 0.25;
+/// The highest render scale the dynamic resolution controller may select.
+pub(crate) const MAX_DYNAMIC_RENDER_SCALE: f32 = 1.0;
+
+pub(crate) const TARGET_FPS: u32 = 60;
+
+/// Number of frame buffers used with the render thread.
+pub(crate) const RENDER_BUFFERS: u8 = 3;
+
+/// Directory receiving the screenshots by default.
+pub(crate) const SCREENSHOT_DIR: &str = "screenshots";
+
+/// Directory receiving the recordings by default.
+pub(crate) const RECORDING_DIR: &str = "recordings";
+
+/// Number of frames rendered by default in headless mode.
+pub(crate) const HEADLESS_FRAMES: u32 = 60;
+
+/// Directory receiving the frames of the render subcommand by default.
+pub(crate) const RENDER_DIR: &str = "renders";
+
+/// File keeping the windowed position and size of the window across runs by default.
+pub(crate) const WINDOW_STATE: &str = "window_state.txt";
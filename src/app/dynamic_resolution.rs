@@ -0,0 +1,147 @@
+/// Adjusts the internal render scale to keep the frame work time within a budget.
+///
+/// The software rendering cost is roughly proportional to the number of rendered pixels, so the
+/// scale is corrected by the square root of the ratio between the target and the measured load.
+pub(super) struct DynamicResolution {
+    pub enabled:   bool,
+    pub min_scale: f32,
+    pub max_scale: f32,
+    scale:         f32,
+    last_change_s: f64,
+}
+
+impl DynamicResolution {
+    /// Minimum time between two changes, so that the frame history reflects the last one.
+    const ADJUST_INTERVAL_S: f64 = 1.0;
+    /// Load (work time / frame budget) below which the scale is raised.
+    const LOWER_LOAD: f32 = 0.6;
+    /// Scale changes are rounded to multiples of this step.
+    const SCALE_STEP: f32 = 0.05;
+    /// Load the controller aims for when it changes the scale.
+    const TARGET_LOAD: f32 = 0.75;
+    /// Load (work time / frame budget) above which the scale is lowered.
+    const UPPER_LOAD: f32 = 0.9;
+
+    pub(super) fn new(enabled: bool, min_scale: f32, max_scale: f32) -> Self {
+        Self { enabled, min_scale, max_scale, scale: max_scale, last_change_s: 0.0 }
+    }
+
+    /// Returns the current render scale, or 1 when the controller is disabled.
+    pub(super) fn scale(&self) -> f32 {
+        if self.enabled { self.scale.clamp(self.min_scale, self.max_scale) } else { 1.0 }
+    }
+
+    /// Feeds the mean work time of the recent frames and the frame budget, both in seconds.
+    /// Returns `true` when the render scale has changed.
+    pub(super) fn update(&mut self, now_s: f64, work_time_s: f32, budget_s: f32) -> bool {
+        if !self.enabled
+            || work_time_s <= 0.0
+            || budget_s <= 0.0
+            || now_s - self.last_change_s < Self::ADJUST_INTERVAL_S
+        {
+            return false;
+        }
+
+        let load = work_time_s / budget_s;
+
+        if (Self::LOWER_LOAD..=Self::UPPER_LOAD).contains(&load) {
+            return false;
+        }
+
+        let current_scale = self.scale();
+        let scale = current_scale * (Self::TARGET_LOAD / load).sqrt();
+        let scale = ((scale / Self::SCALE_STEP).round() * Self::SCALE_STEP)
+            .clamp(self.min_scale, self.max_scale);
+
+        if (scale - current_scale).abs() < Self::SCALE_STEP / 2.0 {
+            return false;
+        }
+
+        log::debug!("Dynamic resolution: scale {current_scale:.2} -> {scale:.2} (load {load:.2})");
+
+        self.scale = scale;
+        self.last_change_s = now_s;
+
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::DynamicResolution;
+
+    const BUDGET_S: f32 = 0.01;
+
+    fn assert_scale(controller: &DynamicResolution, expected: f32) {
+        let scale = controller.scale();
+        assert!((scale - expected).abs() < 1e-4, "scale {scale} instead of {expected}");
+    }
+
+    #[test]
+    fn scale_steps_towards_the_target_load() {
+        let mut controller = DynamicResolution::new(true, 0.25, 1.0);
+        assert_scale(&controller, 1.0);
+
+        // A load of 0.95 is corrected by sqrt(0.75 / 0.95) ~ 0.89, rounded to the 0.05 step.
+        assert!(controller.update(1.0, 0.0095, BUDGET_S));
+        assert_scale(&controller, 0.9);
+
+        // A load of 0.5 is corrected by sqrt(0.75 / 0.5) ~ 1.22.
+        assert!(controller.update(2.0, 0.005, BUDGET_S));
+        assert_scale(&controller, 1.0);
+    }
+
+    #[test]
+    fn loads_within_the_hysteresis_band_keep_the_scale() {
+        let mut controller = DynamicResolution::new(true, 0.25, 1.0);
+
+        for (now_s, load) in [(1.0, 0.6), (2.0, 0.75), (3.0, 0.9)] {
+            assert!(!controller.update(now_s, load * BUDGET_S, BUDGET_S));
+            assert_scale(&controller, 1.0);
+        }
+    }
+
+    #[test]
+    fn scale_is_clamped_to_the_bounds() {
+        let mut controller = DynamicResolution::new(true, 0.5, 0.8);
+        assert_scale(&controller, 0.8);
+
+        // Already at the upper bound, a light load changes nothing.
+        assert!(!controller.update(1.0, 0.001, BUDGET_S));
+        assert_scale(&controller, 0.8);
+
+        assert!(controller.update(2.0, 10.0 * BUDGET_S, BUDGET_S));
+        assert_scale(&controller, 0.5);
+
+        // Already at the lower bound, a heavy load changes nothing.
+        assert!(!controller.update(3.0, 10.0 * BUDGET_S, BUDGET_S));
+        assert_scale(&controller, 0.5);
+
+        assert!(controller.update(4.0, 0.001, BUDGET_S));
+        assert_scale(&controller, 0.8);
+    }
+
+    #[test]
+    fn changes_are_at_least_one_interval_apart() {
+        let mut controller = DynamicResolution::new(true, 0.25, 1.0);
+
+        assert!(!controller.update(0.5, 2.0 * BUDGET_S, BUDGET_S));
+        assert!(controller.update(1.0, 2.0 * BUDGET_S, BUDGET_S));
+        let scale = controller.scale();
+
+        assert!(!controller.update(1.5, 2.0 * BUDGET_S, BUDGET_S));
+        assert_scale(&controller, scale);
+
+        assert!(controller.update(2.0, 2.0 * BUDGET_S, BUDGET_S));
+        assert!(controller.scale() < scale);
+    }
+
+    #[test]
+    fn disabled_controller_keeps_the_native_resolution() {
+        let mut controller = DynamicResolution::new(false, 0.25, 0.5);
+        assert_scale(&controller, 1.0);
+
+        assert!(!controller.update(1.0, 2.0 * BUDGET_S, BUDGET_S));
+        assert_scale(&controller, 1.0);
+    }
+}
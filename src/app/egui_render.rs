@@ -6,8 +6,6 @@ use egui::FullOutput;
 use egui::epaint::textures::TexturesDelta;
 use egui_sdl2_platform::platform::Platform;
 use egui_wgpu_backend::RenderPass;
-use egui_wgpu_backend::wgpu::{self};
-use wgpu::TextureViewDescriptor;
 
 use crate::app::sdl_wgpu::SdlWgpu;
 
@@ -37,11 +35,11 @@ impl<'a> EguiRender<'a> {
         let paint_jobs = platform.tessellate(&full_output);
 
         // // Upload all the resources to the egui render pass
+        // The surface is sized in pixels, the GUI was tessellated at its pixels per point.
         let screen_descriptor = egui_wgpu_backend::ScreenDescriptor {
             physical_width:  sdl_wgpu.surface_configuration.width,
             physical_height: sdl_wgpu.surface_configuration.height,
-            // The sdl scale factor remains constant, so we shall set it to 1
-            scale_factor:    1.0,
+            scale_factor:    full_output.pixels_per_point,
         };
 
         // Add the textures to the egui render pass
@@ -58,15 +56,14 @@ impl<'a> EguiRender<'a> {
             &screen_descriptor,
         );
 
-        let SdlWgpu { frame, encoder, .. } = &mut *sdl_wgpu;
-
-        let frame = frame.as_ref().context("Failed to get frame")?;
-
-        let frame_view = frame.texture.create_view(&TextureViewDescriptor::default());
+        // The textures are kept up to date even when the frame is skipped.
+        let SdlWgpu { frame_view: Some(frame_view), encoder, .. } = &mut *sdl_wgpu else {
+            return Ok(());
+        };
 
         self.egui_pass.execute(
             encoder.as_mut().context("Failed to get the encoder")?,
-            &frame_view,
+            frame_view,
             &paint_jobs,
             &screen_descriptor,
             None,
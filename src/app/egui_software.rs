@@ -0,0 +1,319 @@
+use std::collections::HashMap;
+
+use anyhow::{Context, Result, ensure};
+use egui::epaint::textures::TexturesDelta;
+use egui::epaint::{ClippedPrimitive, ImageData, Primitive, Vertex};
+use egui::{Color32, Pos2, TextureId};
+
+/// A texture of the GUI, in premultiplied sRGBA.
+struct SoftwareTexture {
+    width:  usize,
+    height: usize,
+    pixels: Vec<Color32>,
+}
+
+impl SoftwareTexture {
+    // Returns the texel nearest to the normalized coordinates `uv`.
+    #[allow(
+        clippy::arithmetic_side_effects,
+        clippy::as_conversions,
+        clippy::cast_possible_truncation,
+        clippy::cast_precision_loss,
+        clippy::cast_sign_loss
+    )]
+    fn sample(&self, uv: Pos2) -> Color32 {
+        let x = ((uv.x * self.width as f32) as usize).min(self.width.saturating_sub(1));
+        let y = ((uv.y * self.height as f32) as usize).min(self.height.saturating_sub(1));
+        self.pixels.get(y * self.width + x).copied().unwrap_or(Color32::TRANSPARENT)
+    }
+}
+
+/// Rasterizes the tessellated GUI into an RGBA8 frame on the CPU, as the wgpu egui backend does
+/// on the GPU: textured triangles with interpolated vertex colors, alpha-blended over the frame
+/// and clipped to the rectangle of their primitive.
+pub(super) struct EguiSoftwareRender {
+    textures: HashMap<TextureId, SoftwareTexture>,
+}
+
+impl EguiSoftwareRender {
+    pub(super) fn new() -> Self {
+        Self { textures: HashMap::new() }
+    }
+
+    /// Creates and updates the textures set by `textures_delta`. Call before painting.
+    #[allow(clippy::arithmetic_side_effects)]
+    pub(super) fn update_textures(&mut self, textures_delta: &TexturesDelta) -> Result<()> {
+        for (id, delta) in &textures_delta.set {
+            let [width, height] = delta.image.size();
+            let pixels: Vec<Color32> = match &delta.image {
+                ImageData::Color(image) => image.pixels.clone(),
+                ImageData::Font(image) => image.srgba_pixels(None).collect(),
+            };
+
+            let Some([x, y]) = delta.pos else {
+                self.textures.insert(*id, SoftwareTexture { width, height, pixels });
+                continue;
+            };
+
+            let texture = self.textures.get_mut(id).context("Patch of a missing GUI texture")?;
+            ensure!(
+                x + width <= texture.width && y + height <= texture.height,
+                "GUI texture patch out of bounds"
+            );
+            for (row, patch_row) in pixels.chunks_exact(width.max(1)).enumerate() {
+                let start = (y + row) * texture.width + x;
+                texture.pixels[start..start + width].copy_from_slice(patch_row);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Frees the textures released by `textures_delta`. Call after painting.
+    pub(super) fn free_textures(&mut self, textures_delta: &TexturesDelta) {
+        for id in &textures_delta.free {
+            self.textures.remove(id);
+        }
+    }
+
+    /// Paints `primitives` over `color`, an RGBA8 frame of `width` x `height` pixels.
+    #[allow(
+        clippy::arithmetic_side_effects,
+        clippy::as_conversions,
+        clippy::cast_possible_truncation,
+        clippy::cast_precision_loss,
+        clippy::cast_sign_loss
+    )]
+    pub(super) fn paint(
+        &self,
+        primitives: &[ClippedPrimitive],
+        pixels_per_point: f32,
+        color: &mut [u8],
+        width: u32,
+        height: u32,
+    ) {
+        let (width, height) = (width as usize, height as usize);
+
+        for ClippedPrimitive { clip_rect, primitive } in primitives {
+            // Paint callbacks are specific to the GPU backends.
+            let Primitive::Mesh(mesh) = primitive else {
+                continue;
+            };
+            let Some(texture) = self.textures.get(&mesh.texture_id) else {
+                log::warn!("Missing GUI texture {:?}", mesh.texture_id);
+                continue;
+            };
+
+            // The clip rectangle in pixels, as in the scissor rectangle of the GPU backends.
+            let clip = (
+                ((clip_rect.min.x * pixels_per_point).round().max(0.0) as usize).min(width),
+                ((clip_rect.min.y * pixels_per_point).round().max(0.0) as usize).min(height),
+                ((clip_rect.max.x * pixels_per_point).round().max(0.0) as usize).min(width),
+                ((clip_rect.max.y * pixels_per_point).round().max(0.0) as usize).min(height),
+            );
+            if clip.0 >= clip.2 || clip.1 >= clip.3 {
+                continue;
+            }
+
+            for triangle in mesh.indices.chunks_exact(3) {
+                let vertices = [
+                    mesh.vertices[triangle[0] as usize],
+                    mesh.vertices[triangle[1] as usize],
+                    mesh.vertices[triangle[2] as usize],
+                ];
+                Self::draw_triangle(&vertices, texture, pixels_per_point, clip, color, width);
+            }
+        }
+    }
+
+    // Fills the pixels whose center lies in the triangle, following the top-left rule so that
+    // the pixels on an edge shared by two triangles are blended only once.
+    #[allow(
+        clippy::arithmetic_side_effects,
+        clippy::as_conversions,
+        clippy::cast_possible_truncation,
+        clippy::cast_precision_loss,
+        clippy::cast_sign_loss,
+        clippy::float_cmp,
+        clippy::similar_names
+    )]
+    fn draw_triangle(
+        vertices: &[Vertex; 3],
+        texture: &SoftwareTexture,
+        pixels_per_point: f32,
+        (clip_x0, clip_y0, clip_x1, clip_y1): (usize, usize, usize, usize),
+        color: &mut [u8],
+        width: usize,
+    ) {
+        let [mut p0, mut p1, p2] = vertices.map(|vertex| vertex.pos * pixels_per_point);
+        let [mut v0, mut v1, v2] = *vertices;
+
+        let edge =
+            |a: Pos2, b: Pos2, p: Pos2| (b.x - a.x) * (p.y - a.y) - (b.y - a.y) * (p.x - a.x);
+        let mut area = edge(p0, p1, p2);
+        if area.abs() < f32::EPSILON {
+            return;
+        }
+        // Make the winding consistent, so that the edge functions are positive inside.
+        if area < 0.0 {
+            std::mem::swap(&mut p0, &mut p1);
+            std::mem::swap(&mut v0, &mut v1);
+            area = -area;
+        }
+
+        // With y pointing down, an edge going up, or horizontal going right, is a top or left
+        // edge of a triangle with this winding.
+        let is_top_left = |a: Pos2, b: Pos2| a.y > b.y || (a.y == b.y && b.x > a.x);
+        let inside = |weight: f32, top_left: bool| weight > 0.0 || (weight == 0.0 && top_left);
+        let top_left = [is_top_left(p1, p2), is_top_left(p2, p0), is_top_left(p0, p1)];
+
+        let min_x = p0.x.min(p1.x).min(p2.x).floor().max(clip_x0 as f32) as usize;
+        let min_y = p0.y.min(p1.y).min(p2.y).floor().max(clip_y0 as f32) as usize;
+        let max_x = (p0.x.max(p1.x).max(p2.x).ceil().max(0.0) as usize).min(clip_x1);
+        let max_y = (p0.y.max(p1.y).max(p2.y).ceil().max(0.0) as usize).min(clip_y1);
+
+        let channels = |c: Color32| c.to_array().map(|channel| f32::from(channel) / 255.0);
+        let [c0, c1, c2] = [channels(v0.color), channels(v1.color), channels(v2.color)];
+
+        for y in min_y..max_y {
+            for x in min_x..max_x {
+                let p = Pos2::new(x as f32 + 0.5, y as f32 + 0.5);
+                let (w0, w1, w2) = (edge(p1, p2, p), edge(p2, p0, p), edge(p0, p1, p));
+                if !(inside(w0, top_left[0]) && inside(w1, top_left[1]) && inside(w2, top_left[2]))
+                {
+                    continue;
+                }
+                let (w0, w1, w2) = (w0 / area, w1 / area, w2 / area);
+
+                let uv = Pos2::new(
+                    v0.uv.x * w0 + v1.uv.x * w1 + v2.uv.x * w2,
+                    v0.uv.y * w0 + v1.uv.y * w1 + v2.uv.y * w2,
+                );
+                let texel = channels(texture.sample(uv));
+
+                // Premultiplied source over the destination.
+                let index = (y * width + x) * 4;
+                let Some(pixel) = color.get_mut(index..index + 4) else {
+                    continue;
+                };
+                let mut source = [0.0; 4];
+                for (channel, value) in source.iter_mut().enumerate() {
+                    let vertex = c0[channel] * w0 + c1[channel] * w1 + c2[channel] * w2;
+                    *value = vertex * texel[channel];
+                }
+                let transparency = 1.0 - source[3];
+                for (destination, value) in pixel[..3].iter_mut().zip(source) {
+                    let blended = value * 255.0 + f32::from(*destination) * transparency;
+                    *destination = blended.round().clamp(0.0, 255.0) as u8;
+                }
+                pixel[3] = 255;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::Result;
+    use egui::epaint::textures::TexturesDelta;
+    use egui::epaint::{ClippedPrimitive, ColorImage, ImageDelta, Mesh, Primitive};
+    use egui::{Color32, Rect, TextureId, TextureOptions, pos2};
+
+    use super::EguiSoftwareRender;
+
+    const SIZE: u32 = 4;
+    const TEXTURE: TextureId = TextureId::Managed(0);
+
+    // Returns a renderer holding `pixels` as a texture of `width` x `height` texels.
+    fn renderer(width: usize, height: usize, pixels: Vec<Color32>) -> Result<EguiSoftwareRender> {
+        let image = ColorImage { size: [width, height], pixels };
+        let textures_delta = TexturesDelta {
+            set:  vec![(TEXTURE, ImageDelta::full(image, TextureOptions::NEAREST))],
+            free: Vec::new(),
+        };
+        let mut renderer = EguiSoftwareRender::new();
+        renderer.update_textures(&textures_delta)?;
+        Ok(renderer)
+    }
+
+    // Paints a quad, made of two triangles sharing its diagonal, covering the frame with the
+    // whole texture tinted by `tint` and clipped to `clip_rect`, over a frame filled with
+    // `background`.
+    fn paint_quad(
+        renderer: &EguiSoftwareRender,
+        tint: Color32,
+        clip_rect: Rect,
+        background: [u8; 4],
+    ) -> Vec<[u8; 4]> {
+        let frame_rect = Rect::from_min_max(pos2(0.0, 0.0), pos2(4.0, 4.0));
+        let mut mesh = Mesh::with_texture(TEXTURE);
+        mesh.add_rect_with_uv(frame_rect, Rect::from_min_max(pos2(0.0, 0.0), pos2(1.0, 1.0)), tint);
+        let primitives = [ClippedPrimitive { clip_rect, primitive: Primitive::Mesh(mesh) }];
+
+        let mut color = background.repeat(16);
+        renderer.paint(&primitives, 1.0, &mut color, SIZE, SIZE);
+        color.chunks_exact(4).map(|pixel| [pixel[0], pixel[1], pixel[2], pixel[3]]).collect()
+    }
+
+    fn full_clip() -> Rect {
+        Rect::from_min_max(pos2(0.0, 0.0), pos2(4.0, 4.0))
+    }
+
+    #[test]
+    fn paint_blends_shared_edges_once() -> Result<()> {
+        let renderer = renderer(1, 1, vec![Color32::WHITE])?;
+        let tint = Color32::from_rgba_premultiplied(128, 128, 128, 128);
+
+        let pixels = paint_quad(&renderer, tint, full_clip(), [0, 0, 0, 255]);
+
+        // Blending the pixels on the diagonal twice would give 192.
+        assert!(pixels.iter().all(|&pixel| pixel == [128, 128, 128, 255]), "{pixels:?}");
+        Ok(())
+    }
+
+    #[test]
+    #[allow(clippy::arithmetic_side_effects)]
+    fn paint_clips_to_the_clip_rect() -> Result<()> {
+        let renderer = renderer(1, 1, vec![Color32::WHITE])?;
+        let clip_rect = Rect::from_min_max(pos2(1.0, 1.0), pos2(3.0, 3.0));
+
+        let pixels = paint_quad(&renderer, Color32::WHITE, clip_rect, [0, 0, 0, 255]);
+
+        for (index, &pixel) in pixels.iter().enumerate() {
+            let (x, y) = (index % 4, index / 4);
+            let inside = (1..3).contains(&x) && (1..3).contains(&y);
+            let expected = if inside { [255, 255, 255, 255] } else { [0, 0, 0, 255] };
+            assert_eq!(pixel, expected, "pixel ({x}, {y})");
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn paint_blends_premultiplied_colors() -> Result<()> {
+        let renderer = renderer(1, 1, vec![Color32::WHITE])?;
+        let tint = Color32::from_rgba_premultiplied(64, 0, 0, 128);
+
+        let pixels = paint_quad(&renderer, tint, full_clip(), [100, 200, 50, 255]);
+
+        // Source plus destination times one minus the source alpha, 127 / 255.
+        assert!(pixels.iter().all(|&pixel| pixel == [114, 100, 25, 255]), "{pixels:?}");
+        Ok(())
+    }
+
+    #[test]
+    #[allow(clippy::arithmetic_side_effects)]
+    fn paint_samples_the_nearest_texels() -> Result<()> {
+        let texels = [Color32::RED, Color32::GREEN, Color32::BLUE, Color32::YELLOW];
+        let renderer = renderer(2, 2, texels.to_vec())?;
+
+        let pixels = paint_quad(&renderer, Color32::WHITE, full_clip(), [0, 0, 0, 255]);
+
+        // Each texel covers a 2 x 2 quarter of the frame.
+        for (index, &pixel) in pixels.iter().enumerate() {
+            let (x, y) = (index % 4, index / 4);
+            let [red, green, blue, _] = texels[y / 2 * 2 + x / 2].to_array();
+            assert_eq!(pixel, [red, green, blue, 255], "pixel ({x}, {y})");
+        }
+        Ok(())
+    }
+}
@@ -9,9 +9,20 @@ mod renderer;
 mod world;
 
 use renderer::Renderer;
+pub(super) use renderer::{DirtyRect, FrameCapture, RenderThreadStats, UploadStats};
 use world::World;
+pub(super) use world::{CameraPath, Scene};
 
-pub(super) struct EngineConfiguration {}
+pub(super) struct EngineConfiguration {
+    /// Draw the frames on a dedicated thread.
+    pub render_thread:  bool,
+    /// With the render thread, present each frame once it is complete, while the world update of
+    /// the next frame runs, instead of presenting the last completed frame.
+    pub pipelined:      bool,
+    /// Number of frame buffers used with the render thread (2 for double buffering, 3 for
+    /// triple buffering).
+    pub render_buffers: usize,
+}
 
 pub(super) struct Engine<'a> {
     cfg:      Rc<RefCell<EngineConfiguration>>,
@@ -25,7 +36,19 @@ impl<'a> Engine<'a> {
         screen_quad: ScreenQuad<'a>,
     ) -> Result<Self> {
         let world = World::new()?;
-        let renderer = Renderer::new(screen_quad)?;
+        let renderer = Renderer::new(cfg.clone(), screen_quad)?;
+        Ok(Self { cfg, world, renderer })
+    }
+
+    /// Creates an engine rendering into its frame buffer only, at the given resolution, without
+    /// a window or a GPU.
+    pub(super) fn new_headless(
+        cfg: Rc<RefCell<EngineConfiguration>>,
+        width: u32,
+        height: u32,
+    ) -> Result<Self> {
+        let world = World::new()?;
+        let renderer = Renderer::new_headless(cfg.clone(), width, height)?;
         Ok(Self { cfg, world, renderer })
     }
 
@@ -33,7 +56,58 @@ impl<'a> Engine<'a> {
         self.world.update(dt)
     }
 
-    pub(super) fn render(&mut self) -> Result<()> {
-        self.renderer.render()
+    /// Moves the camera along a scripted path.
+    pub(super) fn set_camera_path(&mut self, camera_path: CameraPath) {
+        self.world.set_camera_path(camera_path);
+    }
+
+    /// Replaces the geometry of the world drawn by the renderer.
+    pub(super) fn set_scene(&mut self, scene: Scene) {
+        self.world.set_scene(scene);
+    }
+
+    /// Handles a change of the internal render resolution.
+    pub(super) fn resize(&mut self, width: u32, height: u32) -> Result<()> {
+        log::debug!("Render resolution changed to {width}x{height}");
+        self.renderer.resize(width, height)
+    }
+
+    /// Returns the frame buffer regions uploaded in the last frame.
+    pub(super) fn dirty_rects(&self) -> &[DirtyRect] {
+        self.renderer.dirty_rects()
+    }
+
+    /// Returns the statistics of the screen texture upload in the last frame.
+    pub(super) fn upload_stats(&self) -> UploadStats {
+        self.renderer.upload_stats()
+    }
+
+    /// Copies the content of the last presented frame, with its depth buffer when `with_depth`
+    /// is set.
+    pub(super) fn capture(&mut self, with_depth: bool) -> FrameCapture {
+        self.renderer.capture(with_depth)
+    }
+
+    /// Returns the size and the RGBA8 pixels of the presented frame, without copying them. Only
+    /// complete without a screen texture, as the mapped upload mode bypasses the frame buffer.
+    pub(super) fn frame(&self) -> (u32, u32, &[u8]) {
+        self.renderer.frame()
+    }
+
+    /// Returns the size and the RGBA8 pixels of the presented frame, to draw overlays over it.
+    pub(super) fn frame_mut(&mut self) -> (u32, u32, &mut [u8]) {
+        self.renderer.frame_mut()
+    }
+
+    /// Returns the render thread statistics, when the render thread is running.
+    pub(super) fn render_thread_stats(&self) -> Option<RenderThreadStats> {
+        self.renderer.render_thread_stats()
+    }
+
+    /// Renders the current state of the world, interpolated by `alpha` between the previous and
+    /// the last update. With the render thread, the frame is drawn from a snapshot of the world
+    /// while the next update runs.
+    pub(super) fn render(&mut self, alpha: f32) -> Result<()> {
+        self.renderer.render(&self.world.snapshot(alpha))
     }
 }
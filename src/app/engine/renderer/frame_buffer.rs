@@ -1,15 +1,38 @@
-#![allow(dead_code)]
-
 use anyhow::{Context, Result};
 
-pub(super) struct FrameBuffer {
+use super::rasterizer::Target;
+
+/// A rectangular region of the frame buffer, in pixels.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub(crate) struct DirtyRect {
+    pub x:      u32,
+    pub y:      u32,
     pub width:  u32,
     pub height: u32,
-    pub color:  Vec<u8>,
-    pub depth:  Vec<f32>,
+}
+
+pub(super) struct FrameBuffer {
+    pub width:   u32,
+    pub height:  u32,
+    pub color:   Vec<u8>,
+    pub depth:   Vec<f32>,
+    // One flag per tile, set when the tile has been modified since the last upload.
+    dirty_tiles: Vec<bool>,
+    tiles_x:     u32,
+    tiles_y:     u32,
+    // Background color of the last frame drawn, or `None` when the content is unknown, in which
+    // case the next frame clears the whole buffer.
+    background:  Option<[u8; 3]>,
+    // Bounds of the triangles of the last frame drawn over its background.
+    drawn:       Option<DirtyRect>,
 }
 
 impl FrameBuffer {
+    /// Depth of the pixels not covered by any triangle.
+    pub(super) const FAR_DEPTH: f32 = 1000.0;
+    /// Side length, in pixels, of the square tiles used for dirty region tracking.
+    pub(super) const TILE_SIZE: u32 = 64;
+
     pub(super) fn new(width: u32, height: u32) -> Result<Self> {
         #[allow(clippy::as_conversions)]
         let width_usize = width as usize;
@@ -26,8 +49,164 @@ impl FrameBuffer {
         let depth_buffer_size = num_pixels;
 
         let color_buffer: Vec<u8> = vec![0; color_buffer_size];
-        let depth_buffer: Vec<f32> = vec![1000.0; depth_buffer_size];
+        let depth_buffer: Vec<f32> = vec![Self::FAR_DEPTH; depth_buffer_size];
+
+        let tiles_x = width.div_ceil(Self::TILE_SIZE);
+        let tiles_y = height.div_ceil(Self::TILE_SIZE);
+
+        #[allow(clippy::as_conversions)]
+        let num_tiles = (tiles_x as usize)
+            .checked_mul(tiles_y as usize)
+            .context("Overflow calculating dirty tiles size")?;
+
+        // The whole buffer must be uploaded the first time.
+        let dirty_tiles = vec![true; num_tiles];
+
+        Ok(Self {
+            color: color_buffer,
+            depth: depth_buffer,
+            width,
+            height,
+            dirty_tiles,
+            tiles_x,
+            tiles_y,
+            background: None,
+            drawn: None,
+        })
+    }
+
+    /// Returns the color and depth buffers as a target to draw into.
+    #[allow(clippy::as_conversions, clippy::arithmetic_side_effects)]
+    pub(super) fn target(&mut self) -> Target<'_> {
+        Target {
+            width:         self.width,
+            height:        self.height,
+            color:         &mut self.color,
+            bytes_per_row: self.width as usize * 4,
+            depth:         &mut self.depth,
+        }
+    }
+
+    /// Restores the `background` of a new frame where the last frame was drawn, or over the whole
+    /// buffer when its content is unknown or the background changed, and marks it as modified.
+    pub(super) fn clear(&mut self, background: [u8; 3]) {
+        if self.background != Some(background) {
+            let mut target = self.target();
+            let bounds = target.bounds();
+            target.clear(bounds, background, Self::FAR_DEPTH);
+            self.background = Some(background);
+            self.drawn = None;
+            self.mark_all_dirty();
+        } else if let Some(drawn) = self.drawn.take() {
+            self.target().clear(drawn, background, Self::FAR_DEPTH);
+            self.mark_dirty(drawn.x, drawn.y, drawn.width, drawn.height);
+        }
+    }
+
+    /// Records the bounds of the triangles drawn over the background since the last clear, and
+    /// marks them as modified.
+    pub(super) fn set_drawn(&mut self, drawn: Option<DirtyRect>) {
+        if let Some(DirtyRect { x, y, width, height }) = drawn {
+            self.mark_dirty(x, y, width, height);
+        }
+        self.drawn = drawn;
+    }
+
+    /// Marks the whole content as modified by something else than the renderer, such as an
+    /// overlay, so that the next frame is drawn over a cleared buffer.
+    pub(super) fn invalidate(&mut self) {
+        self.background = None;
+        self.mark_all_dirty();
+    }
+
+    /// Replaces the modified regions by those differing from `previous`, the frame buffer presented
+    /// before this one: the regions of `previous` not uploaded yet and the bounds of the triangles
+    /// of both frames, or everything when their backgrounds are unknown or differ.
+    pub(super) fn track_changes_from(&mut self, previous: &Self) {
+        if self.background.is_none()
+            || self.background != previous.background
+            || (self.width, self.height) != (previous.width, previous.height)
+        {
+            self.mark_all_dirty();
+            return;
+        }
+
+        self.dirty_tiles.clone_from(&previous.dirty_tiles);
+        for DirtyRect { x, y, width, height } in [previous.drawn, self.drawn].into_iter().flatten()
+        {
+            self.mark_dirty(x, y, width, height);
+        }
+    }
+
+    /// Marks the given region as modified. The region is clipped to the buffer bounds.
+    #[allow(clippy::arithmetic_side_effects, clippy::as_conversions)]
+    pub(super) fn mark_dirty(&mut self, x: u32, y: u32, width: u32, height: u32) {
+        let x_end = x.saturating_add(width).min(self.width);
+        let y_end = y.saturating_add(height).min(self.height);
+
+        if x >= x_end || y >= y_end {
+            return;
+        }
+
+        let (first_tile_x, last_tile_x) = (x / Self::TILE_SIZE, (x_end - 1) / Self::TILE_SIZE);
+        let (first_tile_y, last_tile_y) = (y / Self::TILE_SIZE, (y_end - 1) / Self::TILE_SIZE);
+
+        for tile_y in first_tile_y..=last_tile_y {
+            let row = (tile_y * self.tiles_x) as usize;
+            self.dirty_tiles[row + first_tile_x as usize..=row + last_tile_x as usize].fill(true);
+        }
+    }
+
+    pub(super) fn mark_all_dirty(&mut self) {
+        self.dirty_tiles.fill(true);
+    }
+
+    /// Stores in `rects` the modified regions as a list of non-overlapping rectangles and resets
+    /// the tracking. Horizontal runs of dirty tiles are merged with the run of the row above when
+    /// they cover the same columns.
+    #[allow(clippy::arithmetic_side_effects, clippy::as_conversions)]
+    pub(super) fn take_dirty_rects(&mut self, rects: &mut Vec<DirtyRect>) {
+        rects.clear();
+        // Rects ending on the previous tile row, which can still grow downwards.
+        let mut open: Vec<DirtyRect> = Vec::new();
+        let mut row_rects = Vec::new();
+
+        for tile_y in 0..self.tiles_y {
+            let row = (tile_y * self.tiles_x) as usize;
+            let y = tile_y * Self::TILE_SIZE;
+            let height = Self::TILE_SIZE.min(self.height - y);
+            let mut tile_x = 0;
+
+            while tile_x < self.tiles_x {
+                if !self.dirty_tiles[row + tile_x as usize] {
+                    tile_x += 1;
+                    continue;
+                }
+
+                let run_start = tile_x;
+                while tile_x < self.tiles_x && self.dirty_tiles[row + tile_x as usize] {
+                    tile_x += 1;
+                }
+
+                let x = run_start * Self::TILE_SIZE;
+                let width = (tile_x * Self::TILE_SIZE).min(self.width) - x;
+
+                match open.iter().position(|rect| rect.x == x && rect.width == width) {
+                    Some(index) => {
+                        let mut rect = open.swap_remove(index);
+                        rect.height += height;
+                        row_rects.push(rect);
+                    },
+                    None => row_rects.push(DirtyRect { x, y, width, height }),
+                }
+            }
+
+            rects.append(&mut open);
+            std::mem::swap(&mut open, &mut row_rects);
+        }
+
+        rects.append(&mut open);
 
-        Ok(Self { color: color_buffer, depth: depth_buffer, width, height })
+        self.dirty_tiles.fill(false);
     }
 }
@@ -1,28 +1,388 @@
-use anyhow::Result;
+use std::cell::RefCell;
+use std::rc::Rc;
+use std::time::Instant;
+
+use anyhow::{Context, Result};
 
 mod frame_buffer;
+mod rasterizer;
+mod render_thread;
 
+pub(crate) use frame_buffer::DirtyRect;
 use frame_buffer::FrameBuffer;
+use rasterizer::Target;
+use render_thread::RenderThread;
+pub(crate) use render_thread::RenderThreadStats;
+
+use super::EngineConfiguration;
+use super::world::RenderSnapshot;
+use crate::app::screen_quad::{ScreenQuad, UploadMode};
 
-use crate::app::screen_quad::ScreenQuad;
+/// Statistics of the transfer of a frame to the screen texture.
+#[derive(Copy, Clone, Debug, Default)]
+pub(crate) struct UploadStats {
+    pub bytes:          u64,
+    pub time_s:         f32,
+    /// Frames in which no staging buffer was available and `write_texture` was used instead.
+    pub staging_misses: u64,
+}
+
+/// Copy of the content of the presented frame buffer, detached from the renderer.
+#[derive(Clone, Debug)]
+pub(crate) struct FrameCapture {
+    pub width:  u32,
+    pub height: u32,
+    /// RGBA8 pixels, row by row.
+    pub color:  Vec<u8>,
+    /// Depth values, row by row, or empty when not captured.
+    pub depth:  Vec<f32>,
+}
+
+impl FrameCapture {
+    /// Depth of the pixels not covered by any triangle.
+    pub(crate) const FAR_DEPTH: f32 = FrameBuffer::FAR_DEPTH;
+}
 
 pub(super) struct Renderer<'a> {
-    screen_quad:  ScreenQuad<'a>,
-    frame_buffer: FrameBuffer,
+    cfg:              Rc<RefCell<EngineConfiguration>>,
+    // The screen texture receiving the frames, or `None` when running headless, in which case
+    // the frames are only drawn into the frame buffer.
+    screen_quad:      Option<ScreenQuad<'a>>,
+    // The frame buffer being presented. With the render thread, it is swapped with the last
+    // frame completed by the thread.
+    frame_buffer:     FrameBuffer,
+    render_thread:    Option<RenderThread>,
+    // Regions uploaded in the last frame, kept for the debug overlay.
+    dirty_rects:      Vec<DirtyRect>,
+    upload_stats:     UploadStats,
+    last_upload_mode: UploadMode,
+    // Snapshot of the last rendered frame, to redraw it when it was only drawn into the mapped
+    // staging memory.
+    last_snapshot:    RenderSnapshot,
 }
 
 impl<'a> Renderer<'a> {
-    pub(super) fn new(screen_quad: ScreenQuad<'a>) -> Result<Self> {
+    pub(super) fn new(
+        cfg: Rc<RefCell<EngineConfiguration>>,
+        screen_quad: ScreenQuad<'a>,
+    ) -> Result<Self> {
         let frame_buffer = FrameBuffer::new(screen_quad.width(), screen_quad.height())?;
+        let last_upload_mode = screen_quad.upload_mode();
+
+        Ok(Self {
+            cfg,
+            screen_quad: Some(screen_quad),
+            frame_buffer,
+            render_thread: None,
+            dirty_rects: Vec::new(),
+            upload_stats: UploadStats::default(),
+            last_upload_mode,
+            last_snapshot: RenderSnapshot::default(),
+        })
+    }
 
-        Ok(Self { screen_quad, frame_buffer })
+    /// Creates a renderer drawing into a frame buffer of the given size only, without a window
+    /// or a GPU.
+    pub(super) fn new_headless(
+        cfg: Rc<RefCell<EngineConfiguration>>,
+        width: u32,
+        height: u32,
+    ) -> Result<Self> {
+        Ok(Self {
+            cfg,
+            screen_quad: None,
+            frame_buffer: FrameBuffer::new(width, height)?,
+            render_thread: None,
+            dirty_rects: Vec::new(),
+            upload_stats: UploadStats::default(),
+            last_upload_mode: UploadMode::WriteTexture,
+            last_snapshot: RenderSnapshot::default(),
+        })
     }
 
-    fn color_buffer(&self) -> &[u8] {
-        self.frame_buffer.color.as_slice()
+    /// Reallocates the frame buffer and the screen texture for a new render resolution.
+    pub(super) fn resize(&mut self, width: u32, height: u32) -> Result<()> {
+        if (width, height) == (self.frame_buffer.width, self.frame_buffer.height) {
+            return Ok(());
+        }
+        // The screen texture is resized first, so that an unsupported size leaves the renderer
+        // unchanged.
+        if let Some(screen_quad) = self.screen_quad.as_mut() {
+            screen_quad.resize(width, height)?;
+        }
+        // The buffers of the render thread are reallocated when it restarts.
+        self.render_thread = None;
+        self.frame_buffer = FrameBuffer::new(width, height)?;
+        Ok(())
+    }
+
+    pub(super) fn dirty_rects(&self) -> &[DirtyRect] {
+        &self.dirty_rects
+    }
+
+    pub(super) fn upload_stats(&self) -> UploadStats {
+        self.upload_stats
+    }
+
+    /// Returns the size and the RGBA8 pixels of the presented frame buffer.
+    pub(super) fn frame(&self) -> (u32, u32, &[u8]) {
+        (self.frame_buffer.width, self.frame_buffer.height, &self.frame_buffer.color)
+    }
+
+    /// Returns the size and the RGBA8 pixels of the presented frame buffer, to draw over it.
+    pub(super) fn frame_mut(&mut self) -> (u32, u32, &mut [u8]) {
+        self.frame_buffer.invalidate();
+        (self.frame_buffer.width, self.frame_buffer.height, &mut self.frame_buffer.color)
+    }
+
+    pub(super) fn render_thread_stats(&self) -> Option<RenderThreadStats> {
+        self.render_thread.as_ref().map(RenderThread::stats)
+    }
+
+    /// Renders a frame from `snapshot`. With the render thread, the snapshot is sent to the
+    /// thread and the last completed frame is presented instead.
+    pub(super) fn render(&mut self, snapshot: &RenderSnapshot) -> Result<()> {
+        self.update_render_thread()?;
+
+        // Without a screen texture, the frames are always drawn into the frame buffer.
+        let upload_mode = match self.screen_quad.as_ref().map(ScreenQuad::upload_mode) {
+            // Frames are drawn on the render thread, not into the mapped memory.
+            Some(UploadMode::Mapped) if self.render_thread.is_some() => UploadMode::Staging,
+            Some(upload_mode) => upload_mode,
+            None => UploadMode::WriteTexture,
+        };
+
+        match self.render_thread.as_mut() {
+            Some(render_thread) => {
+                let pipelined = self.cfg.borrow().pipelined;
+                render_thread.swap(&mut self.frame_buffer, pipelined)?;
+                render_thread.request_frame(snapshot.clone())?;
+            },
+            // In mapped upload mode the frame is drawn into the staging memory during the upload.
+            None if upload_mode != UploadMode::Mapped =>
+                Self::draw(snapshot, &mut self.frame_buffer),
+            None => {},
+        }
+
+        self.last_snapshot.clone_from(snapshot);
+
+        if self.screen_quad.is_none() {
+            // Headless, the frame stays in the frame buffer.
+            self.frame_buffer.take_dirty_rects(&mut self.dirty_rects);
+            return Ok(());
+        }
+
+        let upload_start = Instant::now();
+        self.upload(upload_mode, snapshot)?;
+        self.upload_stats.time_s = upload_start.elapsed().as_secs_f32();
+
+        self.screen_quad.as_ref().context("Screen quad missing")?.render()
+    }
+
+    /// Copies the content of the last presented frame, with its depth buffer when `with_depth`
+    /// is set.
+    pub(super) fn capture(&mut self, with_depth: bool) -> FrameCapture {
+        // In mapped upload mode the frame buffer is only drawn when no staging buffer is free.
+        if self.last_upload_mode == UploadMode::Mapped && self.render_thread.is_none() {
+            Self::draw(&self.last_snapshot, &mut self.frame_buffer);
+        }
+
+        FrameCapture {
+            width:  self.frame_buffer.width,
+            height: self.frame_buffer.height,
+            color:  self.frame_buffer.color.clone(),
+            depth:  if with_depth { self.frame_buffer.depth.clone() } else { Vec::new() },
+        }
+    }
+
+    // Starts, restarts or stops the render thread to match the configuration.
+    fn update_render_thread(&mut self) -> Result<()> {
+        let EngineConfiguration { render_thread, render_buffers, .. } = *self.cfg.borrow();
+        let num_buffers = render_buffers.max(2);
+
+        let running_buffers = self.render_thread.as_ref().map(|thread| thread.stats().num_buffers);
+
+        match (render_thread, running_buffers) {
+            (false, Some(_)) => self.render_thread = None,
+            (true, running) if running != Some(num_buffers) => {
+                self.render_thread = None;
+                self.render_thread = Some(RenderThread::new(
+                    self.frame_buffer.width,
+                    self.frame_buffer.height,
+                    num_buffers.saturating_sub(1),
+                )?);
+            },
+            _ => {},
+        }
+
+        Ok(())
     }
 
-    pub(super) fn render(&mut self) -> Result<()> {
-        self.screen_quad.render(self.color_buffer())
+    // Draws the frame described by `snapshot` into `frame_buffer`, marking the modified regions
+    // as dirty. Only the region covered by the previous frame drawn into the buffer is cleared.
+    // With the render thread, it runs on that thread.
+    fn draw(snapshot: &RenderSnapshot, frame_buffer: &mut FrameBuffer) {
+        frame_buffer.clear(snapshot.scene.background);
+        let drawn = rasterizer::draw(&mut frame_buffer.target(), &snapshot.camera, &snapshot.scene);
+        frame_buffer.set_drawn(drawn);
+    }
+
+    // Draws the frame described by `snapshot` into the mapped staging memory, which bypasses the
+    // frame buffer and is cleared entirely as its previous content is unknown.
+    fn draw_mapped(snapshot: &RenderSnapshot, mut target: Target<'_>) {
+        let bounds = target.bounds();
+        target.clear(bounds, snapshot.scene.background, FrameBuffer::FAR_DEPTH);
+        rasterizer::draw(&mut target, &snapshot.camera, &snapshot.scene);
+    }
+
+    // Transfers the frame to the screen texture according to the upload mode, falling back to
+    // `write_texture` when no staging buffer is available.
+    fn upload(&mut self, upload_mode: UploadMode, snapshot: &RenderSnapshot) -> Result<()> {
+        let screen_quad = self.screen_quad.as_mut().context("Screen quad missing")?;
+
+        // The texture content comes from another source after a mode change.
+        if upload_mode != self.last_upload_mode {
+            self.frame_buffer.mark_all_dirty();
+            self.last_upload_mode = upload_mode;
+        }
+
+        let uploaded_bytes = match upload_mode {
+            UploadMode::WriteTexture => None,
+            UploadMode::Staging => {
+                self.frame_buffer.take_dirty_rects(&mut self.dirty_rects);
+                screen_quad.upload_staged(&self.frame_buffer.color, &self.dirty_rects)?
+            },
+            UploadMode::Mapped => {
+                let (width, height) = (self.frame_buffer.width, self.frame_buffer.height);
+                self.dirty_rects.clear();
+                self.dirty_rects.push(DirtyRect { x: 0, y: 0, width, height });
+                let depth = &mut self.frame_buffer.depth;
+                let uploaded_bytes = screen_quad.upload_mapped(|color, bytes_per_row| {
+                    let target = Target { width, height, color, bytes_per_row, depth };
+                    Self::draw_mapped(snapshot, target);
+                })?;
+                if uploaded_bytes.is_none() {
+                    Self::draw(snapshot, &mut self.frame_buffer);
+                    self.frame_buffer.mark_all_dirty();
+                } else {
+                    // The depth buffer no longer matches the colors of the frame buffer.
+                    self.frame_buffer.invalidate();
+                }
+                uploaded_bytes
+            },
+        };
+
+        if let Some(bytes) = uploaded_bytes {
+            self.upload_stats.bytes = bytes;
+            return Ok(());
+        }
+
+        if upload_mode != UploadMode::WriteTexture {
+            self.upload_stats.staging_misses = self.upload_stats.staging_misses.saturating_add(1);
+        }
+
+        if upload_mode != UploadMode::Staging {
+            self.frame_buffer.take_dirty_rects(&mut self.dirty_rects);
+        }
+
+        self.upload_stats.bytes = 0;
+
+        for &DirtyRect { x, y, width, height } in &self.dirty_rects {
+            let bytes =
+                screen_quad.update_texture_region(&self.frame_buffer.color, x, y, width, height)?;
+            self.upload_stats.bytes = self.upload_stats.bytes.saturating_add(bytes);
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use anyhow::Result;
+
+    use super::{DirtyRect, FrameBuffer, RenderSnapshot, Renderer};
+    use crate::app::engine::world::{Scene, Triangle};
+
+    fn snapshot(triangles: Vec<Triangle>) -> RenderSnapshot {
+        let scene = Scene { background: [10, 20, 30], triangles };
+        RenderSnapshot { scene: Arc::new(scene), ..RenderSnapshot::default() }
+    }
+
+    fn dirty_rects(frame_buffer: &mut FrameBuffer) -> Vec<DirtyRect> {
+        let mut rects = Vec::new();
+        frame_buffer.take_dirty_rects(&mut rects);
+        rects
+    }
+
+    #[allow(clippy::arithmetic_side_effects, clippy::as_conversions)]
+    fn pixel(frame_buffer: &FrameBuffer, x: usize, y: usize) -> Result<[u8; 4]> {
+        let index = (y * frame_buffer.width as usize + x) * 4;
+        Ok(<[u8; 4]>::try_from(&frame_buffer.color[index..index + 4])?)
+    }
+
+    #[test]
+    fn draw_marks_the_drawn_regions_dirty() -> Result<()> {
+        let mut frame_buffer = FrameBuffer::new(256, 128)?;
+        // Seen from the default camera, the triangle lies within the second tile of the first row.
+        let triangle = Triangle {
+            vertices: [[-5.0, 0.0, 10.0], [-4.0, 0.0, 10.0], [-4.5, 1.0, 10.0]],
+            color:    [200, 100, 50],
+        };
+        let tile = DirtyRect { x: 64, y: 0, width: 64, height: 64 };
+
+        Renderer::draw(&snapshot(vec![triangle]), &mut frame_buffer);
+        assert_eq!(
+            dirty_rects(&mut frame_buffer),
+            [DirtyRect { x: 0, y: 0, width: 256, height: 128 }]
+        );
+        assert_eq!(pixel(&frame_buffer, 78, 60)?, [200, 100, 50, 255]);
+        assert_eq!(pixel(&frame_buffer, 10, 10)?, [10, 20, 30, 255]);
+
+        // The same frame again only redraws the triangle.
+        Renderer::draw(&snapshot(vec![triangle]), &mut frame_buffer);
+        assert_eq!(dirty_rects(&mut frame_buffer), [tile]);
+
+        // Removing the triangle clears it, after which nothing changes anymore.
+        Renderer::draw(&snapshot(vec![]), &mut frame_buffer);
+        assert_eq!(dirty_rects(&mut frame_buffer), [tile]);
+        assert_eq!(pixel(&frame_buffer, 78, 60)?, [10, 20, 30, 255]);
+        Renderer::draw(&snapshot(vec![]), &mut frame_buffer);
+        assert_eq!(dirty_rects(&mut frame_buffer), []);
+
+        Ok(())
+    }
+
+    #[test]
+    fn swapped_buffers_track_the_changes_from_the_presented_one() -> Result<()> {
+        // Seen from the default camera, the triangles lie within the second and the third tiles
+        // of the first row.
+        let left = Triangle {
+            vertices: [[-5.0, 0.0, 10.0], [-4.0, 0.0, 10.0], [-4.5, 1.0, 10.0]],
+            color:    [200, 100, 50],
+        };
+        let right = Triangle {
+            vertices: [[4.0, 0.0, 10.0], [5.0, 0.0, 10.0], [4.5, 1.0, 10.0]],
+            color:    [50, 100, 200],
+        };
+
+        let mut presented = FrameBuffer::new(256, 128)?;
+        Renderer::draw(&snapshot(vec![left]), &mut presented);
+        dirty_rects(&mut presented);
+
+        // Only the triangles of both frames differ, whatever the buffer held before.
+        let mut swapped = FrameBuffer::new(256, 128)?;
+        Renderer::draw(&snapshot(vec![right]), &mut swapped);
+        swapped.track_changes_from(&presented);
+        assert_eq!(dirty_rects(&mut swapped), [DirtyRect { x: 64, y: 0, width: 128, height: 64 }]);
+
+        // A buffer of unknown content differs everywhere.
+        swapped.invalidate();
+        swapped.track_changes_from(&presented);
+        assert_eq!(dirty_rects(&mut swapped), [DirtyRect { x: 0, y: 0, width: 256, height: 128 }]);
+
+        Ok(())
     }
 }
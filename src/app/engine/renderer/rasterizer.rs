@@ -0,0 +1,230 @@
+use super::frame_buffer::DirtyRect;
+use crate::app::engine::world::{Camera, Scene, Triangle};
+
+/// Distance of the near clipping plane from the camera.
+const NEAR: f32 = 0.1;
+/// Vertical field of view, in degrees.
+const FOV_Y_DEG: f32 = 60.0;
+
+/// Color and depth buffers to draw into, either the frame buffer or the mapped staging memory.
+pub(super) struct Target<'a> {
+    pub width:         u32,
+    pub height:        u32,
+    /// RGBA8 pixels, rows `bytes_per_row` bytes apart.
+    pub color:         &'a mut [u8],
+    pub bytes_per_row: usize,
+    /// View space depth of the pixels, rows `width` values apart.
+    pub depth:         &'a mut [f32],
+}
+
+impl Target<'_> {
+    /// Fills `rect` with `background` at the far depth.
+    #[allow(clippy::arithmetic_side_effects, clippy::as_conversions)]
+    pub(super) fn clear(&mut self, rect: DirtyRect, background: [u8; 3], far: f32) {
+        let [r, g, b] = background;
+        let (x, width) = (rect.x as usize, rect.width as usize);
+        for y in rect.y..rect.y.saturating_add(rect.height).min(self.height) {
+            let color_row = y as usize * self.bytes_per_row + x * 4;
+            for pixel in self.color[color_row..color_row + width * 4].chunks_exact_mut(4) {
+                pixel.copy_from_slice(&[r, g, b, 255]);
+            }
+            let depth_row = y as usize * self.width as usize + x;
+            self.depth[depth_row..depth_row + width].fill(far);
+        }
+    }
+
+    /// Returns the whole target as a rectangle.
+    pub(super) const fn bounds(&self) -> DirtyRect {
+        DirtyRect { x: 0, y: 0, width: self.width, height: self.height }
+    }
+}
+
+/// Draws the triangles of `scene` seen from `camera` over the content of `target`, with depth
+/// testing, and returns the bounds of the drawn region, if any. Triangles are drawn from both
+/// sides, clipped against the near plane.
+pub(super) fn draw(target: &mut Target<'_>, camera: &Camera, scene: &Scene) -> Option<DirtyRect> {
+    let view = View::new(camera, target.height);
+    let mut drawn: Option<DirtyRect> = None;
+
+    for triangle in &scene.triangles {
+        let [r, g, b] = triangle.color;
+        let (polygon, len) = view.clip(triangle);
+        // The clipped polygon is a convex fan of one or two triangles.
+        for index in 2..len {
+            let vertices = [polygon[0], polygon[index.saturating_sub(1)], polygon[index]]
+                .map(|vertex| view.project(vertex, target.width, target.height));
+            if let Some(rect) = fill(target, vertices, [r, g, b, 255]) {
+                drawn = Some(drawn.map_or(rect, |drawn| union(drawn, rect)));
+            }
+        }
+    }
+
+    drawn
+}
+
+// Transform from world space to view space, and projection to the screen.
+struct View {
+    position: [f32; 3],
+    yaw:      (f32, f32),
+    pitch:    (f32, f32),
+    // Distance of the projection plane, in pixels.
+    focal_px: f32,
+}
+
+impl View {
+    #[allow(clippy::as_conversions, clippy::cast_precision_loss, clippy::arithmetic_side_effects)]
+    fn new(camera: &Camera, height: u32) -> Self {
+        let yaw = camera.yaw_deg.to_radians();
+        let pitch = camera.pitch_deg.to_radians();
+        Self {
+            position: camera.position,
+            yaw:      (yaw.sin(), yaw.cos()),
+            pitch:    (pitch.sin(), pitch.cos()),
+            focal_px: height as f32 / 2.0 / (FOV_Y_DEG.to_radians() / 2.0).tan(),
+        }
+    }
+
+    // Returns `point` in view space, where the camera looks down +z with +y up. With a yaw and a
+    // pitch of 0 the camera looks down +z in world space.
+    #[allow(clippy::arithmetic_side_effects)]
+    fn transform(&self, point: [f32; 3]) -> [f32; 3] {
+        let [x, y, z] =
+            [point[0] - self.position[0], point[1] - self.position[1], point[2] - self.position[2]];
+        let ((sin_yaw, cos_yaw), (sin_pitch, cos_pitch)) = (self.yaw, self.pitch);
+        let (x, z) = (x * cos_yaw - z * sin_yaw, x * sin_yaw + z * cos_yaw);
+        let (y, z) = (y * cos_pitch - z * sin_pitch, y * sin_pitch + z * cos_pitch);
+        [x, y, z]
+    }
+
+    // Returns the vertices in view space of the part of `triangle` in front of the near plane,
+    // with their count.
+    #[allow(clippy::arithmetic_side_effects)]
+    fn clip(&self, triangle: &Triangle) -> ([[f32; 3]; 4], usize) {
+        let vertices = triangle.vertices.map(|vertex| self.transform(vertex));
+        let mut polygon = [[0.0; 3]; 4];
+        let mut len = 0;
+
+        for (index, &start) in vertices.iter().enumerate() {
+            let end = vertices[(index + 1) % 3];
+            if start[2] >= NEAR {
+                polygon[len] = start;
+                len += 1;
+            }
+            if (start[2] >= NEAR) != (end[2] >= NEAR) {
+                let t = (NEAR - start[2]) / (end[2] - start[2]);
+                polygon[len] =
+                    [start[0] + (end[0] - start[0]) * t, start[1] + (end[1] - start[1]) * t, NEAR];
+                len += 1;
+            }
+        }
+
+        (polygon, len)
+    }
+
+    // Projects a point in view space to the screen, returning its pixel coordinates with the
+    // inverse of its depth, which is linear in screen space.
+    #[allow(clippy::as_conversions, clippy::cast_precision_loss, clippy::arithmetic_side_effects)]
+    fn project(&self, [x, y, z]: [f32; 3], width: u32, height: u32) -> [f32; 3] {
+        let inv_z = 1.0 / z;
+        [
+            width as f32 / 2.0 + x * self.focal_px * inv_z,
+            height as f32 / 2.0 - y * self.focal_px * inv_z,
+            inv_z,
+        ]
+    }
+}
+
+// Fills the triangle with screen space `vertices`, made of the pixel coordinates and the inverse
+// depth, with `color` where it passes the depth test. The pixels whose center lies on an edge
+// shared by two triangles are only drawn once, by following the top-left rule. Returns the
+// bounds of the pixels which may have been drawn.
+#[allow(
+    clippy::arithmetic_side_effects,
+    clippy::as_conversions,
+    clippy::cast_possible_truncation,
+    clippy::cast_precision_loss,
+    clippy::cast_sign_loss
+)]
+fn fill(target: &mut Target<'_>, mut vertices: [[f32; 3]; 3], color: [u8; 4]) -> Option<DirtyRect> {
+    let mut area = edge(vertices[0], vertices[1], vertices[2]);
+    if !area.is_finite() || area.abs() <= f32::EPSILON {
+        return None;
+    }
+    // Both windings are drawn, ordered so that the edge functions are positive inside.
+    if area < 0.0 {
+        vertices.swap(1, 2);
+        area = -area;
+    }
+
+    let min = |axis: usize| vertices.iter().map(|vertex| vertex[axis]).fold(f32::MAX, f32::min);
+    let max = |axis: usize| vertices.iter().map(|vertex| vertex[axis]).fold(f32::MIN, f32::max);
+    let x_start = min(0).floor().max(0.0) as u32;
+    let y_start = min(1).floor().max(0.0) as u32;
+    let x_end = (max(0).ceil().max(0.0) as u32).min(target.width);
+    let y_end = (max(1).ceil().max(0.0) as u32).min(target.height);
+    if x_start >= x_end || y_start >= y_end {
+        return None;
+    }
+
+    // Edges opposite to each vertex, with whether the pixels on them are drawn.
+    let edges = [(1, 2), (2, 0), (0, 1)].map(|(start, end): (usize, usize)| {
+        let (a, b) = (vertices[start], vertices[end]);
+        (a, b, is_top_left(a, b))
+    });
+
+    for y in y_start..y_end {
+        for x in x_start..x_end {
+            let center = [x as f32 + 0.5, y as f32 + 0.5, 0.0];
+            let weights = edges.map(|(a, b, top_left)| (edge(a, b, center), top_left));
+            if !weights.iter().all(|&(weight, top_left)| weight > 0.0 || top_left && weight >= 0.0)
+            {
+                continue;
+            }
+
+            let inv_z = weights
+                .iter()
+                .zip(&vertices)
+                .map(|(&(weight, _), vertex)| weight * vertex[2])
+                .sum::<f32>()
+                / area;
+            let z = 1.0 / inv_z;
+            let depth_index = y as usize * target.width as usize + x as usize;
+            if z >= target.depth[depth_index] {
+                continue;
+            }
+            target.depth[depth_index] = z;
+
+            let color_index = y as usize * target.bytes_per_row + x as usize * 4;
+            target.color[color_index..color_index + 4].copy_from_slice(&color);
+        }
+    }
+
+    Some(DirtyRect {
+        x:      x_start,
+        y:      y_start,
+        width:  x_end - x_start,
+        height: y_end - y_start,
+    })
+}
+
+// Twice the signed area of the triangle `a`, `b`, `c`, positive when clockwise on the screen.
+#[allow(clippy::arithmetic_side_effects)]
+fn edge(a: [f32; 3], b: [f32; 3], c: [f32; 3]) -> f32 {
+    (b[0] - a[0]) * (c[1] - a[1]) - (b[1] - a[1]) * (c[0] - a[0])
+}
+
+// Whether the edge from `a` to `b` of a clockwise triangle is a top edge, horizontal above the
+// triangle, or a left edge, going up.
+#[allow(clippy::float_cmp)]
+fn is_top_left(a: [f32; 3], b: [f32; 3]) -> bool {
+    (a[1] == b[1] && b[0] > a[0]) || b[1] < a[1]
+}
+
+// Returns the smallest rectangle containing `a` and `b`.
+#[allow(clippy::arithmetic_side_effects)]
+fn union(a: DirtyRect, b: DirtyRect) -> DirtyRect {
+    let (x, y) = (a.x.min(b.x), a.y.min(b.y));
+    let x_end = (a.x + a.width).max(b.x + b.width);
+    let y_end = (a.y + a.height).max(b.y + b.height);
+    DirtyRect { x, y, width: x_end - x, height: y_end - y }
+}
@@ -0,0 +1,256 @@
+use std::sync::mpsc::{self, Receiver, Sender, SyncSender, TryRecvError, TrySendError};
+use std::thread::{self, JoinHandle};
+use std::time::Instant;
+
+use anyhow::{Context, Result, anyhow};
+
+use super::Renderer;
+use super::frame_buffer::FrameBuffer;
+use crate::app::engine::world::RenderSnapshot;
+
+/// Swap and latency statistics of the render thread.
+#[derive(Copy, Clone, Debug, Default)]
+pub(crate) struct RenderThreadStats {
+    pub num_buffers: usize,
+    /// Completed frames presented.
+    pub swaps:       u64,
+    /// Completed frames replaced by a newer one before being presented.
+    pub dropped:     u64,
+    /// Presentations without a new completed frame, which show the previous one again.
+    pub repeats:     u64,
+    /// Requests skipped because the render thread was still busy with the previous one.
+    pub skipped:     u64,
+    /// Smoothed time (in seconds) from a frame request to its presentation.
+    pub latency_s:   f32,
+}
+
+struct RenderJob {
+    snapshot:  RenderSnapshot,
+    requested: Instant,
+}
+
+struct RenderedFrame {
+    frame_buffer: FrameBuffer,
+    requested:    Instant,
+}
+
+/// Draws frames into a pool of frame buffers on a dedicated thread, while the main thread
+/// presents the last completed one.
+pub(super) struct RenderThread {
+    job_tx:  Option<SyncSender<RenderJob>>,
+    free_tx: Option<Sender<FrameBuffer>>,
+    done_rx: Receiver<RenderedFrame>,
+    handle:  Option<JoinHandle<()>>,
+    // Requested frames not received back yet.
+    pending: usize,
+    stats:   RenderThreadStats,
+}
+
+impl RenderThread {
+    /// Weight of the last sample in the smoothed latency.
+    const LATENCY_SMOOTHING: f32 = 0.1;
+
+    /// Starts the thread with `num_buffers` frame buffers of the given size to draw into, in
+    /// addition to the one held by the main thread for presentation.
+    pub(super) fn new(width: u32, height: u32, num_buffers: usize) -> Result<Self> {
+        // A single pending request: the thread renders at most one frame ahead of the requests.
+        let (job_tx, job_rx) = mpsc::sync_channel::<RenderJob>(1);
+        let (free_tx, free_rx) = mpsc::channel::<FrameBuffer>();
+        let (done_tx, done_rx) = mpsc::channel::<RenderedFrame>();
+
+        for _ in 0..num_buffers {
+            free_tx.send(FrameBuffer::new(width, height)?)?;
+        }
+
+        let handle = thread::Builder::new()
+            .name("render".into())
+            .spawn(move || Self::run(&job_rx, &free_rx, &done_tx))
+            .context("Failed to spawn the render thread")?;
+
+        log::info!("Render thread started with {} frame buffers", num_buffers.saturating_add(1));
+
+        Ok(Self {
+            job_tx: Some(job_tx),
+            free_tx: Some(free_tx),
+            done_rx,
+            handle: Some(handle),
+            pending: 0,
+            stats: RenderThreadStats {
+                num_buffers: num_buffers.saturating_add(1),
+                ..RenderThreadStats::default()
+            },
+        })
+    }
+
+    fn run(
+        job_rx: &Receiver<RenderJob>,
+        free_rx: &Receiver<FrameBuffer>,
+        done_tx: &Sender<RenderedFrame>,
+    ) {
+        while let Ok(RenderJob { snapshot, requested }) = job_rx.recv() {
+            let Ok(mut frame_buffer) = free_rx.recv() else {
+                break;
+            };
+
+            Renderer::draw(&snapshot, &mut frame_buffer);
+
+            if done_tx.send(RenderedFrame { frame_buffer, requested }).is_err() {
+                break;
+            }
+        }
+    }
+
+    pub(super) fn stats(&self) -> RenderThreadStats {
+        self.stats
+    }
+
+    /// Asks the thread to draw a new frame from `snapshot`, unless it is still busy with a
+    /// previous request.
+    pub(super) fn request_frame(&mut self, snapshot: RenderSnapshot) -> Result<()> {
+        let job_tx = self.job_tx.as_ref().context("Render thread stopped")?;
+
+        match job_tx.try_send(RenderJob { snapshot, requested: Instant::now() }) {
+            Ok(()) => {
+                self.pending = self.pending.saturating_add(1);
+                Ok(())
+            },
+            Err(TrySendError::Full(_)) => {
+                self.stats.skipped = self.stats.skipped.saturating_add(1);
+                Ok(())
+            },
+            Err(TrySendError::Disconnected(_)) => Err(anyhow!("Render thread disconnected")),
+        }
+    }
+
+    /// Swaps `presented` with the most recent completed frame, if any, and gives the buffers
+    /// no longer needed back to the thread. With `wait`, blocks until the oldest requested frame
+    /// is complete. Returns `true` when a new frame has been swapped in.
+    pub(super) fn swap(&mut self, presented: &mut FrameBuffer, wait: bool) -> Result<bool> {
+        let mut latest: Option<RenderedFrame> = None;
+
+        if wait && self.pending > 0 {
+            let frame =
+                self.done_rx.recv().map_err(|_err| anyhow!("Render thread disconnected"))?;
+            self.pending = self.pending.saturating_sub(1);
+            latest = Some(frame);
+        }
+
+        loop {
+            match self.done_rx.try_recv() {
+                Ok(frame) => {
+                    self.pending = self.pending.saturating_sub(1);
+                    if let Some(older) = latest.replace(frame) {
+                        self.stats.dropped = self.stats.dropped.saturating_add(1);
+                        self.release(older.frame_buffer)?;
+                    }
+                },
+                Err(TryRecvError::Empty) => break,
+                Err(TryRecvError::Disconnected) =>
+                    return Err(anyhow!("Render thread disconnected")),
+            }
+        }
+
+        let Some(RenderedFrame { mut frame_buffer, requested }) = latest else {
+            self.stats.repeats = self.stats.repeats.saturating_add(1);
+            return Ok(false);
+        };
+
+        let latency_s = requested.elapsed().as_secs_f32();
+        self.stats.latency_s += (latency_s - self.stats.latency_s) * Self::LATENCY_SMOOTHING;
+        self.stats.swaps = self.stats.swaps.saturating_add(1);
+
+        // The screen texture holds the presented frame, not the previous content of the new one.
+        frame_buffer.track_changes_from(presented);
+        let previous = std::mem::replace(presented, frame_buffer);
+        self.release(previous)?;
+
+        Ok(true)
+    }
+
+    fn release(&self, frame_buffer: FrameBuffer) -> Result<()> {
+        let free_tx = self.free_tx.as_ref().context("Render thread stopped")?;
+        free_tx.send(frame_buffer).map_err(|_err| anyhow!("Render thread disconnected"))
+    }
+}
+
+impl Drop for RenderThread {
+    fn drop(&mut self) {
+        self.job_tx = None;
+        self.free_tx = None;
+        if let Some(handle) = self.handle.take()
+            && handle.join().is_err()
+        {
+            log::error!("Render thread panicked");
+        }
+        log::info!("Render thread stopped");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::thread;
+    use std::time::Duration;
+
+    use anyhow::{Context, Result};
+
+    use super::{FrameBuffer, RenderSnapshot, RenderThread};
+
+    const WIDTH: u32 = 64;
+    const HEIGHT: u32 = 32;
+
+    #[test]
+    fn swaps_count_new_frames_and_repeats() -> Result<()> {
+        let mut render_thread = RenderThread::new(WIDTH, HEIGHT, 2)?;
+        let mut presented = FrameBuffer::new(WIDTH, HEIGHT)?;
+
+        assert!(!render_thread.swap(&mut presented, false)?);
+
+        render_thread.request_frame(RenderSnapshot::default())?;
+        assert!(render_thread.swap(&mut presented, true)?);
+        // Nothing is pending anymore, waiting does not block.
+        assert!(!render_thread.swap(&mut presented, true)?);
+
+        let stats = render_thread.stats();
+        assert_eq!(
+            (stats.num_buffers, stats.swaps, stats.dropped, stats.repeats, stats.skipped),
+            (3, 1, 0, 2, 0)
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn swaps_drop_the_older_completed_frames() -> Result<()> {
+        let mut render_thread = RenderThread::new(WIDTH, HEIGHT, 2)?;
+        let mut presented = FrameBuffer::new(WIDTH, HEIGHT)?;
+
+        // The second request is only accepted once the thread has taken the first one.
+        while render_thread.pending < 2 {
+            render_thread.request_frame(RenderSnapshot::default())?;
+        }
+        // Gives the thread time to complete both frames.
+        thread::sleep(Duration::from_millis(200));
+
+        assert!(render_thread.swap(&mut presented, true)?);
+        assert_eq!(render_thread.pending, 0);
+
+        let stats = render_thread.stats();
+        assert_eq!((stats.swaps, stats.dropped, stats.repeats), (1, 1, 0));
+
+        Ok(())
+    }
+
+    #[test]
+    fn drop_joins_the_thread() -> Result<()> {
+        let mut render_thread = RenderThread::new(WIDTH, HEIGHT, 2)?;
+        let free_tx = render_thread.free_tx.clone().context("Render thread stopped")?;
+        render_thread.request_frame(RenderSnapshot::default())?;
+
+        drop(render_thread);
+
+        // The thread has returned, dropping the receiver of the free buffers.
+        assert!(free_tx.send(FrameBuffer::new(WIDTH, HEIGHT)?).is_err());
+
+        Ok(())
+    }
+}
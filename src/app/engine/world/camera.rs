@@ -0,0 +1,112 @@
+use std::fs;
+use std::path::Path;
+
+use anyhow::{Context, Result, ensure};
+
+/// Point of view of a frame.
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub(crate) struct Camera {
+    pub position:  [f32; 3],
+    /// Rotation around the vertical axis, in degrees.
+    pub yaw_deg:   f32,
+    /// Rotation around the horizontal axis, in degrees.
+    pub pitch_deg: f32,
+}
+
+impl Camera {
+    #[allow(clippy::arithmetic_side_effects)]
+    fn lerp(&self, other: &Self, t: f32) -> Self {
+        let lerp = |a: f32, b: f32| a + (b - a) * t;
+        Self {
+            position:  [
+                lerp(self.position[0], other.position[0]),
+                lerp(self.position[1], other.position[1]),
+                lerp(self.position[2], other.position[2]),
+            ],
+            yaw_deg:   lerp(self.yaw_deg, other.yaw_deg),
+            pitch_deg: lerp(self.pitch_deg, other.pitch_deg),
+        }
+    }
+}
+
+/// Scripted camera motion, linearly interpolated between keyframes.
+#[derive(Clone, Debug)]
+pub(crate) struct CameraPath {
+    /// Keyframes sorted by time in seconds.
+    keyframes: Vec<(f64, Camera)>,
+}
+
+impl CameraPath {
+    /// Loads a camera path from a text file with one keyframe per line, made of the time in
+    /// seconds, the position and the yaw and pitch in degrees, separated by whitespace:
+    ///
+    /// ```text
+    /// # time  x    y    z     yaw  pitch
+    /// 0.0     0.0  1.0  -5.0  0.0  0.0
+    /// 2.0     5.0  1.0  -5.0  45.0 -10.0
+    /// ```
+    ///
+    /// Empty lines and lines starting with `#` are ignored. The times must be increasing.
+    pub(crate) fn load(path: &Path) -> Result<Self> {
+        let text = fs::read_to_string(path)
+            .with_context(|| format!("Failed to read camera path {}", path.display()))?;
+
+        let mut keyframes: Vec<(f64, Camera)> = vec![];
+        for (index, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let line_number = index.saturating_add(1);
+            let keyframe = Self::parse_keyframe(line)
+                .with_context(|| format!("Invalid keyframe at {}:{line_number}", path.display()))?;
+            if let Some(&(last_time_s, _)) = keyframes.last() {
+                ensure!(
+                    keyframe.0 > last_time_s,
+                    "Keyframe time not increasing at {}:{line_number}",
+                    path.display()
+                );
+            }
+            keyframes.push(keyframe);
+        }
+
+        ensure!(!keyframes.is_empty(), "Camera path {} has no keyframes", path.display());
+        Ok(Self { keyframes })
+    }
+
+    fn parse_keyframe(line: &str) -> Result<(f64, Camera)> {
+        let values = line
+            .split_whitespace()
+            .map(str::parse::<f64>)
+            .collect::<Result<Vec<_>, _>>()
+            .context("Expected numbers")?;
+        ensure!(values.len() == 6, "Expected 6 values, found {}", values.len());
+
+        #[allow(clippy::as_conversions, clippy::cast_possible_truncation)]
+        let camera = Camera {
+            position:  [values[1] as f32, values[2] as f32, values[3] as f32],
+            yaw_deg:   values[4] as f32,
+            pitch_deg: values[5] as f32,
+        };
+        Ok((values[0], camera))
+    }
+
+    /// Returns the camera at `time_s`, held at the first and the last keyframes outside of the
+    /// path.
+    #[allow(
+        clippy::arithmetic_side_effects,
+        clippy::as_conversions,
+        clippy::cast_possible_truncation
+    )]
+    pub(crate) fn sample(&self, time_s: f64) -> Camera {
+        let next = self.keyframes.partition_point(|&(keyframe_s, _)| keyframe_s <= time_s);
+        let previous = next.checked_sub(1).and_then(|previous| self.keyframes.get(previous));
+        match (previous, self.keyframes.get(next)) {
+            (Some(&(start_s, start)), Some(&(end_s, end))) =>
+                start.lerp(&end, ((time_s - start_s) / (end_s - start_s)) as f32),
+            (Some(&(_, camera)), None) | (None, Some(&(_, camera))) => camera,
+            (None, None) => Camera::default(),
+        }
+    }
+}
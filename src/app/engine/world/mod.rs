@@ -1,13 +1,64 @@
+use std::sync::Arc;
+
 use anyhow::Result;
 
-pub(super) struct World {}
+mod camera;
+mod scene;
+
+pub(crate) use camera::{Camera, CameraPath};
+pub(crate) use scene::{Scene, Triangle};
+
+/// Immutable copy of the world state needed to draw a frame, so that rendering can proceed
+/// while the world is updated for the next frame.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct RenderSnapshot {
+    /// Point of view at the simulated time interpolated between the last two updates.
+    pub camera: Camera,
+    pub scene:  Arc<Scene>,
+}
+
+pub(super) struct World {
+    time_s:          f64,
+    previous_time_s: f64,
+    camera:          Camera,
+    /// Scripted camera motion overriding `camera`, if any.
+    camera_path:     Option<CameraPath>,
+    scene:           Arc<Scene>,
+}
 
 impl World {
     pub(super) fn new() -> Result<Self> {
-        Ok(Self {})
+        Ok(Self {
+            time_s:          0.0,
+            previous_time_s: 0.0,
+            camera:          Camera::default(),
+            camera_path:     None,
+            scene:           Arc::new(Scene::demo()),
+        })
     }
 
     pub(super) fn update(&mut self, dt: f32) -> Result<()> {
+        self.previous_time_s = self.time_s;
+        self.time_s += f64::from(dt);
         Ok(())
     }
+
+    /// Moves the camera along `camera_path` from now on, following the simulated time.
+    pub(super) fn set_camera_path(&mut self, camera_path: CameraPath) {
+        self.camera = camera_path.sample(self.time_s);
+        self.camera_path = Some(camera_path);
+    }
+
+    /// Replaces the static geometry of the world.
+    pub(super) fn set_scene(&mut self, scene: Scene) {
+        self.scene = Arc::new(scene);
+    }
+
+    /// Extracts the state needed to render the current frame, interpolated by `alpha` between
+    /// the previous and the last update.
+    pub(super) fn snapshot(&self, alpha: f32) -> RenderSnapshot {
+        let time_s = self.previous_time_s + (self.time_s - self.previous_time_s) * f64::from(alpha);
+        let camera = self.camera_path.as_ref().map_or(self.camera, |path| path.sample(time_s));
+        RenderSnapshot { camera, scene: self.scene.clone() }
+    }
 }
@@ -0,0 +1,171 @@
+use std::fs;
+use std::path::Path;
+
+use anyhow::{Context, Result, bail, ensure};
+
+/// Flat colored triangle, with its vertices in world space.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub(crate) struct Triangle {
+    pub vertices: [[f32; 3]; 3],
+    /// RGB color.
+    pub color:    [u8; 3],
+}
+
+/// Static geometry of the world, drawn by the renderer.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub(crate) struct Scene {
+    /// RGB color of the pixels not covered by any triangle.
+    pub background: [u8; 3],
+    pub triangles:  Vec<Triangle>,
+}
+
+impl Scene {
+    /// Returns a few shapes on a floor, in front of the default camera.
+    pub(crate) fn demo() -> Self {
+        let mut scene = Self { background: [24, 32, 48], triangles: Vec::new() };
+        scene.push_quad(
+            [[-6.0, -1.0, 2.0], [6.0, -1.0, 2.0], [6.0, -1.0, 20.0], [-6.0, -1.0, 20.0]],
+            [72, 80, 64],
+        );
+        scene.push_quad(
+            [[-2.0, -1.0, 6.0], [0.0, -1.0, 6.0], [0.0, 1.0, 6.0], [-2.0, 1.0, 6.0]],
+            [200, 48, 48],
+        );
+        scene.push_quad(
+            [[-0.5, -0.5, 8.0], [1.5, -0.5, 8.0], [1.5, 1.5, 8.0], [-0.5, 1.5, 8.0]],
+            [48, 96, 200],
+        );
+        scene.triangles.push(Triangle {
+            vertices: [[2.0, -1.0, 7.0], [4.0, -1.0, 9.0], [3.0, 1.5, 8.0]],
+            color:    [220, 200, 64],
+        });
+        scene
+    }
+
+    /// Loads a scene from a text file with one element per line, made of its kind followed by
+    /// its values separated by whitespace: the background color, then triangles and quads given
+    /// by their corners in world space and their color, with components from 0 to 255:
+    ///
+    /// ```text
+    /// # kind      corners (x y z)...                         color (r g b)
+    /// background                                             24 32 48
+    /// triangle    2 -1 7   4 -1 9   3 1.5 8                  220 200 64
+    /// quad        -2 -1 6  0 -1 6   0 1 6     -2 1 6         200 48 48
+    /// ```
+    ///
+    /// Empty lines and lines starting with `#` are ignored. The background is black by default.
+    pub(crate) fn load(path: &Path) -> Result<Self> {
+        let text = fs::read_to_string(path)
+            .with_context(|| format!("Failed to read scene {}", path.display()))?;
+        Self::parse(&text).with_context(|| format!("Invalid scene {}", path.display()))
+    }
+
+    fn parse(text: &str) -> Result<Self> {
+        let mut scene = Self::default();
+        for (index, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let line_number = index.saturating_add(1);
+            scene
+                .parse_element(line)
+                .with_context(|| format!("Invalid element at line {line_number}"))?;
+        }
+
+        Ok(scene)
+    }
+
+    #[allow(clippy::arithmetic_side_effects)]
+    fn parse_element(&mut self, line: &str) -> Result<()> {
+        let mut tokens = line.split_whitespace();
+        let kind = tokens.next().context("Expected an element kind")?;
+        let values = tokens
+            .map(str::parse::<f32>)
+            .collect::<Result<Vec<_>, _>>()
+            .context("Expected numbers")?;
+
+        let num_corners = match kind {
+            "background" => 0,
+            "triangle" => 3,
+            "quad" => 4,
+            _ => bail!("Unknown element kind {kind}"),
+        };
+        let num_values = num_corners * 3 + 3;
+        ensure!(values.len() == num_values, "Expected {num_values} values, found {}", values.len());
+
+        let (coordinates, color) = values.split_at(num_corners * 3);
+        let color = Self::parse_color(color)?;
+        let corner = |index: usize| {
+            let corner = &coordinates[index * 3..index * 3 + 3];
+            [corner[0], corner[1], corner[2]]
+        };
+
+        match kind {
+            "triangle" => {
+                let vertices = [corner(0), corner(1), corner(2)];
+                self.triangles.push(Triangle { vertices, color });
+            },
+            "quad" => self.push_quad([corner(0), corner(1), corner(2), corner(3)], color),
+            _ => self.background = color,
+        }
+        Ok(())
+    }
+
+    #[allow(clippy::as_conversions, clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+    fn parse_color(values: &[f32]) -> Result<[u8; 3]> {
+        ensure!(
+            values.iter().all(|value| (0.0..=255.0).contains(value)),
+            "Color components must be between 0 and 255"
+        );
+        Ok([values[0] as u8, values[1] as u8, values[2] as u8])
+    }
+
+    /// Adds the quad with the given corners, in order around its edges, as two triangles.
+    pub(crate) fn push_quad(&mut self, corners: [[f32; 3]; 4], color: [u8; 3]) {
+        let [a, b, c, d] = corners;
+        self.triangles.push(Triangle { vertices: [a, b, c], color });
+        self.triangles.push(Triangle { vertices: [a, c, d], color });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::Result;
+
+    use super::{Scene, Triangle};
+
+    #[test]
+    fn parse_reads_the_elements() -> Result<()> {
+        let scene = Scene::parse(
+            "# A triangle over a quad\nbackground 1 2 3\n\ntriangle 0 0 1  1 0 1  0 1 1  4 5 \
+             6\nquad 0 0 2  1 0 2  1 1 2  0 1 2  7 8 9\n",
+        )?;
+
+        assert_eq!(scene.background, [1, 2, 3]);
+        assert_eq!(scene.triangles.len(), 3);
+        assert_eq!(
+            scene.triangles[0],
+            Triangle {
+                vertices: [[0.0, 0.0, 1.0], [1.0, 0.0, 1.0], [0.0, 1.0, 1.0]],
+                color:    [4, 5, 6],
+            }
+        );
+        assert_eq!(
+            scene.triangles[2],
+            Triangle {
+                vertices: [[0.0, 0.0, 2.0], [1.0, 1.0, 2.0], [0.0, 1.0, 2.0]],
+                color:    [7, 8, 9],
+            }
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn parse_rejects_invalid_elements() {
+        assert!(Scene::parse("sphere 0 0 0 1 255 0 0").is_err());
+        assert!(Scene::parse("triangle 0 0 1 1 0 1 0 1 1 255 0").is_err());
+        assert!(Scene::parse("background 0 0 256").is_err());
+    }
+}
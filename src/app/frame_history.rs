@@ -1,18 +1,28 @@
+use std::rc::Rc;
+
 use egui::util::History;
 
+use super::clock::Clock;
+
 pub(super) struct FrameHistory {
+    clock:       Rc<dyn Clock>,
     frame_times: History<f32>,
+    work_times:  History<f32>,
 }
 
 impl FrameHistory {
-    pub(super) fn new(max_len: usize, max_age: f32) -> Self {
-        Self { frame_times: History::new(0..max_len, max_age) }
+    pub(super) fn new(clock: Rc<dyn Clock>, max_len: usize, max_age: f32) -> Self {
+        Self {
+            clock,
+            frame_times: History::new(0..max_len, max_age),
+            work_times: History::new(0..max_len, max_age),
+        }
     }
 
-    /// Call this once per frame.
-    /// `now` is the current time in seconds (e.g. from a high-precision timer).
+    /// Call this once per frame, timestamped with the current time of the clock.
     /// `previous_frame_time` is the duration (in seconds) that the last frame took.
-    pub(super) fn on_new_frame(&mut self, now: f64, maybe_previous_frame_time: Option<f32>) {
+    pub(super) fn on_new_frame(&mut self, maybe_previous_frame_time: Option<f32>) {
+        let now = self.clock.now_s();
         let previous_frame_time = maybe_previous_frame_time.unwrap_or_default();
         // Update the latest entry with the known frame time.
         if let Some(latest) = self.frame_times.latest_mut() {
@@ -22,6 +32,17 @@ impl FrameHistory {
         self.frame_times.add(now, previous_frame_time);
     }
 
+    /// Records the time (in seconds) spent working on the last frame, excluding the time spent
+    /// sleeping or waiting for the presentation.
+    pub(super) fn on_work_time(&mut self, work_time: f32) {
+        self.work_times.add(self.clock.now_s(), work_time);
+    }
+
+    /// Returns the average work time (in seconds) over the history window.
+    pub(super) fn mean_work_time(&self) -> f32 {
+        self.work_times.average().unwrap_or_default()
+    }
+
     /// Returns the average frame time (in seconds) over the history window.
     pub(super) fn mean_frame_time(&self) -> f32 {
         self.frame_times.average().unwrap_or_default()
@@ -34,3 +55,45 @@ impl FrameHistory {
         if mean_interval > 0.0 { 1.0 / mean_interval } else { 0.0 }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::rc::Rc;
+
+    use super::FrameHistory;
+    use crate::app::clock::ManualClock;
+
+    #[test]
+    fn stats_follow_the_clock() {
+        let clock = Rc::new(ManualClock::new(1000));
+        let mut history = FrameHistory::new(clock.clone(), 100, 10.0);
+
+        let mut previous_frame_time = None;
+        for _ in 0..10 {
+            history.on_new_frame(previous_frame_time);
+            clock.advance(4);
+            history.on_work_time(0.004);
+            clock.advance(16);
+            previous_frame_time = Some(0.020);
+        }
+
+        assert!((history.fps() - 50.0).abs() < 1e-3);
+        assert!((history.mean_frame_time() - 0.020).abs() < 1e-6);
+        assert!((history.mean_work_time() - 0.004).abs() < 1e-6);
+    }
+
+    #[test]
+    fn stats_forget_the_frames_older_than_the_window() {
+        let clock = Rc::new(ManualClock::new(1000));
+        let mut history = FrameHistory::new(clock.clone(), 100, 1.0);
+
+        // Slow frames first, then fast frames filling the whole window.
+        for frame_time_ms in [100_u16; 10].into_iter().chain([10; 200]) {
+            history.on_new_frame(Some(f32::from(frame_time_ms) / 1e3));
+            clock.advance(frame_time_ms.into());
+        }
+
+        assert!((history.fps() - 100.0).abs() < 1e-2);
+        assert!((history.mean_frame_time() - 0.010).abs() < 1e-6);
+    }
+}
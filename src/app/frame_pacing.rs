@@ -0,0 +1,294 @@
+use std::rc::Rc;
+use std::thread;
+use std::time::Duration;
+
+use clap::ValueEnum;
+use egui_wgpu_backend::wgpu::PresentMode;
+
+use super::clock::Clock;
+
+/// How the main loop waits for the start of the next frame.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, ValueEnum)]
+pub(crate) enum PacingMode {
+    /// Sleep for the rest of the frame time, at the timer resolution of the system.
+    Sleep,
+    /// Sleep for the rest of the frame time, then spin until the frame time is reached.
+    Hybrid,
+    /// Let the blocking present of a vsync'ed surface pace the frames.
+    Vsync,
+    /// Run frames back to back.
+    Uncapped,
+    /// Sleep and spin until fixed deadlines spaced by the target frame time, so that the errors
+    /// of individual frames do not accumulate.
+    FixedFps,
+}
+
+impl PacingMode {
+    pub(super) const ALL: [Self; 5] =
+        [Self::Sleep, Self::Hybrid, Self::Vsync, Self::Uncapped, Self::FixedFps];
+
+    pub(super) const fn label(self) -> &'static str {
+        match self {
+            Self::Sleep => "Sleep only",
+            Self::Hybrid => "Hybrid sleep/spin",
+            Self::Vsync => "Vsync",
+            Self::Uncapped => "Uncapped",
+            Self::FixedFps => "Fixed FPS",
+        }
+    }
+
+    /// Returns the default mode with the given present mode: the blocking present of the vsync'ed
+    /// modes paces the frames, the others are paced by a hybrid wait.
+    pub(super) const fn default_for(present_mode: PresentMode) -> Self {
+        match present_mode {
+            PresentMode::Fifo | PresentMode::FifoRelaxed | PresentMode::AutoVsync => Self::Vsync,
+            PresentMode::Immediate | PresentMode::Mailbox | PresentMode::AutoNoVsync =>
+                Self::Hybrid,
+        }
+    }
+}
+
+/// Frame pacing statistics, smoothed over the recent frames.
+#[derive(Copy, Clone, Debug, Default)]
+pub(crate) struct PacingStats {
+    /// Mean absolute difference (in seconds) between the frame time and the target frame time.
+    pub error_s:     f32,
+    /// Mean time (in seconds) slept beyond the requested duration.
+    pub oversleep_s: f32,
+}
+
+/// Waits between frames according to the pacing mode.
+pub(super) struct FramePacer {
+    pub mode:         PacingMode,
+    clock:            Rc<dyn Clock>,
+    /// Clock ticks per second.
+    frequency:        f64,
+    /// Target frame time in ticks.
+    frame_ticks:      f64,
+    /// Time slept beyond the request in the previous frame, deducted from the next sleep.
+    over_sleep_ticks: f64,
+    /// Consecutive frames without waiting, in the hybrid and uncapped modes.
+    num_delays:       u32,
+    /// End of the current frame in the fixed FPS mode.
+    deadline:         Option<f64>,
+    stats:            PacingStats,
+}
+
+impl FramePacer {
+    /// Frames run without waiting before the thread yields once.
+    const NUM_DELAYS_PER_YIELD: u32 = 16;
+    /// Time (in seconds) before a fixed FPS deadline spent spinning instead of sleeping.
+    const SPIN_MARGIN_S: f64 = 0.002;
+    /// Weight of the last sample in the smoothed statistics.
+    const STATS_SMOOTHING: f32 = 0.1;
+
+    pub(super) fn new(mode: PacingMode, clock: Rc<dyn Clock>, target_fps: u32) -> Self {
+        #[allow(clippy::as_conversions, clippy::cast_precision_loss)]
+        let frequency = clock.frequency() as f64;
+        Self {
+            mode,
+            clock,
+            frequency,
+            frame_ticks: frequency / f64::from(target_fps.max(1)),
+            over_sleep_ticks: 0.0,
+            num_delays: 0,
+            deadline: None,
+            stats: PacingStats::default(),
+        }
+    }
+
+    /// Re-derives the mode after the present mode changed from `previous` to `present_mode`, when
+    /// it is the default of the previous present mode or relies on a blocking present that may
+    /// no longer happen. Other explicitly selected modes are kept.
+    pub(super) fn on_present_mode_change(
+        &mut self,
+        previous: PresentMode,
+        present_mode: PresentMode,
+    ) {
+        if self.mode == PacingMode::Vsync || self.mode == PacingMode::default_for(previous) {
+            self.mode = PacingMode::default_for(present_mode);
+        }
+    }
+
+    pub(super) fn stats(&self) -> PacingStats {
+        self.stats
+    }
+
+    /// Waits for the end of the frame started at `before_ticks`, whose work completed at
+    /// `after_ticks`. Returns the end of the frame.
+    #[allow(
+        clippy::arithmetic_side_effects,
+        clippy::as_conversions,
+        clippy::cast_precision_loss,
+        clippy::cast_possible_truncation,
+        clippy::cast_sign_loss
+    )]
+    pub(super) fn wait(&mut self, before_ticks: u64, after_ticks: u64) -> u64 {
+        let clock = self.clock.clone();
+
+        if self.mode != PacingMode::FixedFps {
+            self.deadline = None;
+        }
+
+        let (end_ticks, over_sleep_ticks) = match self.mode {
+            PacingMode::Sleep | PacingMode::Hybrid => {
+                let proc_ticks = (after_ticks - before_ticks) as f64 + self.over_sleep_ticks;
+
+                if self.frame_ticks >= proc_ticks {
+                    let sleep_ticks = self.frame_ticks - proc_ticks;
+                    self.sleep(sleep_ticks);
+                    let mut end_ticks = clock.now();
+                    let over_sleep_ticks = (end_ticks - after_ticks) as f64 - sleep_ticks;
+                    if self.mode == PacingMode::Hybrid && over_sleep_ticks < 0. {
+                        clock.spin_until(before_ticks + self.frame_ticks.ceil() as u64);
+                        end_ticks = clock.now();
+                    }
+                    self.num_delays = 0;
+                    (end_ticks, over_sleep_ticks.max(0.))
+                } else {
+                    self.delay();
+                    (clock.now(), 0.)
+                }
+            },
+            PacingMode::Vsync | PacingMode::Uncapped => {
+                self.delay();
+                (clock.now(), 0.)
+            },
+            PacingMode::FixedFps => {
+                let deadline = match self.deadline {
+                    // Restart the schedule when more than a frame behind, rather than catching up
+                    // with a burst of short frames.
+                    Some(deadline) if after_ticks as f64 <= deadline + self.frame_ticks => deadline,
+                    _ => before_ticks as f64 + self.frame_ticks,
+                };
+                self.deadline = Some(deadline + self.frame_ticks);
+
+                let sleep_ticks =
+                    deadline - after_ticks as f64 - Self::SPIN_MARGIN_S * self.frequency;
+                self.sleep(sleep_ticks);
+                let over_sleep_ticks = (clock.now() - after_ticks) as f64 - sleep_ticks.max(0.);
+                clock.spin_until(deadline.ceil() as u64);
+                let end_ticks = clock.now();
+                (end_ticks, if sleep_ticks > 0. { over_sleep_ticks.max(0.) } else { 0. })
+            },
+        };
+
+        self.over_sleep_ticks = over_sleep_ticks;
+
+        let error_s =
+            (((end_ticks - before_ticks) as f64 - self.frame_ticks) / self.frequency).abs() as f32;
+        let oversleep_s = (over_sleep_ticks / self.frequency) as f32;
+        self.stats.error_s += (error_s - self.stats.error_s) * Self::STATS_SMOOTHING;
+        self.stats.oversleep_s += (oversleep_s - self.stats.oversleep_s) * Self::STATS_SMOOTHING;
+
+        end_ticks
+    }
+
+    #[allow(
+        clippy::as_conversions,
+        clippy::cast_possible_truncation,
+        clippy::cast_sign_loss,
+        clippy::arithmetic_side_effects
+    )]
+    fn sleep(&self, ticks: f64) {
+        let sleep_time_ms = (ticks / self.frequency * 1e3) as u64;
+        if sleep_time_ms > 0 {
+            self.clock.sleep(Duration::from_millis(sleep_time_ms));
+        }
+    }
+
+    // Yields the thread once in a while when frames run without waiting.
+    fn delay(&mut self) {
+        self.num_delays = self.num_delays.saturating_add(1);
+        if self.num_delays >= Self::NUM_DELAYS_PER_YIELD {
+            thread::yield_now();
+            self.num_delays = 0;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::rc::Rc;
+
+    use egui_wgpu_backend::wgpu::PresentMode;
+
+    use super::{FramePacer, PacingMode};
+    use crate::app::clock::{Clock, ManualClock};
+
+    // Runs a frame starting now and taking `work_ticks`, and returns the end of the frame.
+    fn run_frame(pacer: &mut FramePacer, clock: &ManualClock, work_ticks: u64) -> u64 {
+        let before_ticks = clock.now();
+        clock.advance(work_ticks);
+        pacer.wait(before_ticks, clock.now())
+    }
+
+    fn new_pacer(mode: PacingMode, target_fps: u32) -> (FramePacer, Rc<ManualClock>) {
+        // One tick per millisecond.
+        let clock = Rc::new(ManualClock::new(1000));
+        (FramePacer::new(mode, clock.clone(), target_fps), clock)
+    }
+
+    #[test]
+    fn sleep_waits_for_whole_milliseconds() {
+        let (mut pacer_50, clock_50) = new_pacer(PacingMode::Sleep, 50);
+        assert_eq!(run_frame(&mut pacer_50, &clock_50, 5), 20);
+
+        // A frame of 16.67 ms only sleeps 11 of the 11.67 ms left.
+        let (mut pacer_60, clock_60) = new_pacer(PacingMode::Sleep, 60);
+        assert_eq!(run_frame(&mut pacer_60, &clock_60, 5), 16);
+    }
+
+    #[test]
+    fn hybrid_spins_for_the_rest_of_the_frame() {
+        let (mut pacer, clock) = new_pacer(PacingMode::Hybrid, 60);
+        assert_eq!(run_frame(&mut pacer, &clock, 5), 17);
+        assert_eq!(run_frame(&mut pacer, &clock, 5), 34);
+        assert!(pacer.stats().oversleep_s.abs() < 1e-6);
+    }
+
+    #[test]
+    fn uncapped_and_vsync_do_not_wait() {
+        for mode in [PacingMode::Uncapped, PacingMode::Vsync] {
+            let (mut pacer, clock) = new_pacer(mode, 50);
+            assert_eq!(run_frame(&mut pacer, &clock, 5), 5);
+            assert_eq!(run_frame(&mut pacer, &clock, 5), 10);
+        }
+    }
+
+    #[test]
+    fn fixed_fps_keeps_the_schedule_of_late_frames() {
+        let (mut pacer, clock) = new_pacer(PacingMode::FixedFps, 50);
+        assert_eq!(run_frame(&mut pacer, &clock, 5), 20);
+        // A late frame ends when its work completes, and the next one catches up.
+        assert_eq!(run_frame(&mut pacer, &clock, 30), 50);
+        assert_eq!(run_frame(&mut pacer, &clock, 1), 60);
+    }
+
+    #[test]
+    fn fixed_fps_restarts_the_schedule_more_than_a_frame_behind() {
+        let (mut pacer, clock) = new_pacer(PacingMode::FixedFps, 50);
+        assert_eq!(run_frame(&mut pacer, &clock, 5), 20);
+        assert_eq!(run_frame(&mut pacer, &clock, 100), 120);
+        // No burst of short frames to catch up with the missed deadlines.
+        assert_eq!(run_frame(&mut pacer, &clock, 1), 140);
+        assert_eq!(run_frame(&mut pacer, &clock, 1), 160);
+    }
+
+    #[test]
+    fn present_mode_change_rederives_the_default_and_vsync_modes() {
+        let (mut pacer, _) = new_pacer(PacingMode::Vsync, 60);
+        pacer.on_present_mode_change(PresentMode::Fifo, PresentMode::Immediate);
+        assert_eq!(pacer.mode, PacingMode::Hybrid);
+        pacer.on_present_mode_change(PresentMode::Immediate, PresentMode::Fifo);
+        assert_eq!(pacer.mode, PacingMode::Vsync);
+
+        // Explicit modes not relying on vsync are kept.
+        for mode in [PacingMode::Sleep, PacingMode::Uncapped, PacingMode::FixedFps] {
+            let (mut explicit_pacer, _) = new_pacer(mode, 60);
+            explicit_pacer.on_present_mode_change(PresentMode::Fifo, PresentMode::Mailbox);
+            explicit_pacer.on_present_mode_change(PresentMode::Mailbox, PresentMode::Fifo);
+            assert_eq!(explicit_pacer.mode, mode);
+        }
+    }
+}
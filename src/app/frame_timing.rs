@@ -0,0 +1,83 @@
+use std::rc::Rc;
+
+use super::AppConfiguration;
+use super::clock::Clock;
+use super::frame_history::FrameHistory;
+use super::frame_pacing::FramePacer;
+use super::timestep::Timestep;
+
+/// Timing of the frames of a main loop: the time elapsed between the frames to advance the
+/// simulation by, the wait for the end of each frame and the frame statistics, all driven by the
+/// same clock.
+pub(super) struct FrameTiming {
+    pub timestep:      Timestep,
+    pub frame_pacer:   FramePacer,
+    pub frame_history: FrameHistory,
+    clock:             Rc<dyn Clock>,
+    /// Duration (in seconds) of a frame at the target frame rate.
+    frame_s:           f64,
+    /// Start of the previous frame, `None` before the first one.
+    last_frame_ticks:  Option<u64>,
+}
+
+impl FrameTiming {
+    /// Number of frames and age (in seconds) of the frames kept for the statistics.
+    const HISTORY_LEN: usize = 300;
+    const HISTORY_MAX_AGE_S: f32 = 1.0;
+    /// Simulation steps run at most in addition to the one of each frame, to catch up with real
+    /// time.
+    const MAX_FRAME_SKIPS: u32 = 5;
+
+    pub(super) fn new(cfg: &AppConfiguration, clock: Rc<dyn Clock>) -> Self {
+        let frame_s = 1.0 / f64::from(cfg.target_fps.max(1));
+        Self {
+            timestep: Timestep::new(
+                cfg.timestep_mode,
+                frame_s,
+                Self::MAX_FRAME_SKIPS.saturating_add(1),
+            ),
+            frame_pacer: FramePacer::new(cfg.pacing_mode, clock.clone(), cfg.target_fps),
+            frame_history: FrameHistory::new(
+                clock.clone(),
+                Self::HISTORY_LEN,
+                Self::HISTORY_MAX_AGE_S,
+            ),
+            clock,
+            frame_s,
+            last_frame_ticks: None,
+        }
+    }
+
+    /// Returns the duration (in seconds) of a frame at the target frame rate.
+    pub(super) const fn frame_s(&self) -> f64 {
+        self.frame_s
+    }
+
+    /// Returns the time (in seconds) elapsed between the start of the previous frame and
+    /// `before_ticks`, the start of the current one. The first frame lasts one frame time.
+    #[allow(clippy::as_conversions, clippy::cast_precision_loss)]
+    pub(super) fn elapsed_s(&mut self, before_ticks: u64) -> f64 {
+        match self.last_frame_ticks.replace(before_ticks) {
+            Some(last_frame_ticks) =>
+                self.clock.to_seconds(before_ticks.saturating_sub(last_frame_ticks) as f64),
+            None => self.frame_s,
+        }
+    }
+
+    /// Waits for the end of the frame started at `before_ticks`, whose work completed at
+    /// `after_ticks` after `work_ticks` spent working, and records it in the frame history.
+    /// Returns the end of the frame.
+    #[allow(clippy::as_conversions, clippy::cast_precision_loss, clippy::cast_possible_truncation)]
+    pub(super) fn end_frame(
+        &mut self,
+        before_ticks: u64,
+        after_ticks: u64,
+        work_ticks: u64,
+    ) -> u64 {
+        let end_ticks = self.frame_pacer.wait(before_ticks, after_ticks);
+        let frame_ticks = end_ticks.saturating_sub(before_ticks);
+        self.frame_history.on_new_frame(Some(self.clock.to_seconds(frame_ticks as f64) as f32));
+        self.frame_history.on_work_time(self.clock.to_seconds(work_ticks as f64) as f32);
+        end_ticks
+    }
+}
@@ -0,0 +1,220 @@
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result, bail, ensure};
+
+use super::engine::FrameCapture;
+
+/// Comparison of a rendered frame with a stored reference image.
+pub(super) struct GoldenConfiguration {
+    /// The reference image, in any of the screenshot file formats.
+    pub reference:            PathBuf,
+    /// Largest difference of a color channel for which two pixels are considered equal.
+    pub tolerance:            u8,
+    /// Largest number of differing pixels for which the frame still matches the reference.
+    pub max_differing_pixels: u64,
+    /// Write the rendered frame as the new reference instead of comparing it.
+    pub update:               bool,
+}
+
+/// Result of the comparison of a frame with its reference.
+struct Comparison {
+    differing_pixels: u64,
+    max_difference:   u8,
+    /// RGB8 image showing the differing pixels in red over the dimmed reference.
+    diff:             Vec<u8>,
+}
+
+/// Compares `capture` with the reference image, or replaces the reference when updating. On a
+/// mismatch, the rendered frame and a diff image are written next to the reference, named after
+/// it with the `-actual` and `-diff` suffixes, and an error is returned.
+pub(super) fn check(cfg: &GoldenConfiguration, capture: &FrameCapture) -> Result<()> {
+    let rgb = to_rgb(capture);
+
+    if cfg.update {
+        save_rgb(&cfg.reference, &rgb, capture.width, capture.height)?;
+        log::info!("Reference image {} updated", cfg.reference.display());
+        return Ok(());
+    }
+
+    let reference = image::open(&cfg.reference)
+        .with_context(|| format!("Failed to read reference image {}", cfg.reference.display()))?
+        .to_rgb8();
+    ensure!(
+        reference.dimensions() == (capture.width, capture.height),
+        "Frame size {}x{} differs from the size {}x{} of the reference image {}",
+        capture.width,
+        capture.height,
+        reference.width(),
+        reference.height(),
+        cfg.reference.display()
+    );
+
+    let comparison = compare(reference.as_raw(), &rgb, cfg.tolerance);
+    if comparison.differing_pixels <= cfg.max_differing_pixels {
+        log::info!(
+            "Frame matches the reference image {} ({} differing pixels)",
+            cfg.reference.display(),
+            comparison.differing_pixels
+        );
+        return Ok(());
+    }
+
+    let actual_path = suffixed_path(&cfg.reference, "actual");
+    let diff_path = suffixed_path(&cfg.reference, "diff");
+    save_rgb(&actual_path, &rgb, capture.width, capture.height)?;
+    save_rgb(&diff_path, &comparison.diff, capture.width, capture.height)?;
+
+    bail!(
+        "Frame differs from the reference image {}: {} pixels differ by up to {} (tolerance {}, \
+         at most {} differing pixels allowed), see {} and {}",
+        cfg.reference.display(),
+        comparison.differing_pixels,
+        comparison.max_difference,
+        cfg.tolerance,
+        cfg.max_differing_pixels,
+        actual_path.display(),
+        diff_path.display()
+    )
+}
+
+// Compares two RGB8 images of the same size, pixel by pixel.
+fn compare(reference: &[u8], actual: &[u8], tolerance: u8) -> Comparison {
+    let mut differing_pixels = 0_u64;
+    let mut max_difference = 0;
+    let mut diff = Vec::with_capacity(reference.len());
+
+    for (expected, pixel) in reference.chunks_exact(3).zip(actual.chunks_exact(3)) {
+        let difference =
+            expected.iter().zip(pixel).map(|(&a, &b)| a.abs_diff(b)).max().unwrap_or_default();
+        max_difference = max_difference.max(difference);
+
+        if difference > tolerance {
+            differing_pixels = differing_pixels.saturating_add(1);
+            diff.extend_from_slice(&[255, 0, 0]);
+        } else {
+            let dimmed = expected.iter().copied().max().unwrap_or_default() / 4;
+            diff.extend_from_slice(&[dimmed, dimmed, dimmed]);
+        }
+    }
+
+    Comparison { differing_pixels, max_difference, diff }
+}
+
+// Drops the alpha channel, which is not stored in the reference images.
+fn to_rgb(capture: &FrameCapture) -> Vec<u8> {
+    capture.color.chunks_exact(4).flat_map(|pixel| [pixel[0], pixel[1], pixel[2]]).collect()
+}
+
+// Writes an RGB8 image, in the format given by the extension of `path`.
+fn save_rgb(path: &Path, rgb: &[u8], width: u32, height: u32) -> Result<()> {
+    image::save_buffer(path, rgb, width, height, image::ExtendedColorType::Rgb8)
+        .with_context(|| format!("Failed to write {}", path.display()))
+}
+
+// Appends `suffix` to the file name of `path`, keeping its extension.
+fn suffixed_path(path: &Path, suffix: &str) -> PathBuf {
+    let stem = path.file_stem().map(|stem| stem.to_string_lossy()).unwrap_or_default();
+    let name = format!("{stem}-{suffix}");
+    match path.extension() {
+        Some(extension) => path.with_file_name(name).with_extension(extension),
+        None => path.with_file_name(name),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+    use std::path::Path;
+    use std::rc::Rc;
+    use std::{env, fs};
+
+    use anyhow::{Context, Result, ensure};
+
+    use super::{GoldenConfiguration, check, compare};
+    use crate::app::engine::{Engine, EngineConfiguration, FrameCapture, Scene};
+
+    /// Directory of the scenes checked by the golden tests, named after their file.
+    const SCENES_DIR: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/scenes");
+    /// Directory of the reference images of the scenes, named after them.
+    const REFERENCES_DIR: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/golden");
+    /// Size of the frames rendered from the scenes.
+    const SCENE_FRAME_SIZE: (u32, u32) = (160, 90);
+    /// Pixels allowed to differ from the reference of a scene, so that rounding differences of
+    /// the floating point functions between platforms only flip a few pixels on the edges.
+    const SCENE_MAX_DIFFERING_PIXELS: u64 = 16;
+
+    /// Renders each scene of the scenes directory headlessly, from the default camera, and
+    /// compares it with its reference image, or replaces the references when `update` is set.
+    /// Fails when any scene differs from its reference, after checking all of them.
+    fn check_scenes(update: bool) -> Result<()> {
+        let mut scenes = fs::read_dir(SCENES_DIR)
+            .with_context(|| format!("Failed to read directory {SCENES_DIR}"))?
+            .map(|entry| entry.map(|entry| entry.path()))
+            .collect::<Result<Vec<_>, _>>()
+            .with_context(|| format!("Failed to read directory {SCENES_DIR}"))?;
+        scenes.retain(|path| path.extension().is_some_and(|extension| extension == "scene"));
+        scenes.sort();
+        ensure!(!scenes.is_empty(), "No scene found in {SCENES_DIR}");
+
+        if update {
+            fs::create_dir_all(REFERENCES_DIR)
+                .with_context(|| format!("Failed to create directory {REFERENCES_DIR}"))?;
+        }
+
+        let mut failures = Vec::new();
+        for path in &scenes {
+            let name = path.file_stem().map(|stem| stem.to_string_lossy()).unwrap_or_default();
+            let cfg = GoldenConfiguration {
+                reference: Path::new(REFERENCES_DIR).join(&*name).with_extension("png"),
+                tolerance: 2,
+                max_differing_pixels: SCENE_MAX_DIFFERING_PIXELS,
+                update,
+            };
+            if let Err(err) = render_scene(path).and_then(|capture| check(&cfg, &capture)) {
+                log::error!("Scene {name}: {err:#}");
+                failures.push(name);
+            }
+        }
+
+        ensure!(
+            failures.is_empty(),
+            "{} of the {} scenes failed: {}",
+            failures.len(),
+            scenes.len(),
+            failures.join(", ")
+        );
+        Ok(())
+    }
+
+    // Renders the first frame of the scene at `path`, synchronously.
+    fn render_scene(path: &Path) -> Result<FrameCapture> {
+        let engine_cfg = Rc::new(RefCell::new(EngineConfiguration {
+            render_thread:  false,
+            pipelined:      false,
+            render_buffers: 2,
+        }));
+        let (width, height) = SCENE_FRAME_SIZE;
+        let mut engine = Engine::new_headless(engine_cfg, width, height)?;
+        engine.set_scene(Scene::load(path)?);
+        engine.render(1.0)?;
+        Ok(engine.capture(false))
+    }
+
+    #[test]
+    fn scenes_match_their_reference() -> Result<()> {
+        // `UPDATE_GOLDEN=1 cargo test` regenerates the references.
+        check_scenes(env::var_os("UPDATE_GOLDEN").is_some())
+    }
+
+    #[test]
+    fn compare_counts_the_pixels_beyond_the_tolerance() {
+        let reference = [10, 20, 30, 100, 100, 100, 0, 0, 0];
+        let actual = [12, 20, 30, 100, 103, 100, 0, 0, 0];
+
+        let comparison = compare(&reference, &actual, 2);
+
+        assert_eq!(comparison.differing_pixels, 1);
+        assert_eq!(comparison.max_difference, 3);
+        assert_eq!(comparison.diff, [7, 7, 7, 255, 0, 0, 0, 0, 0]);
+    }
+}
@@ -3,17 +3,44 @@ use std::collections::BTreeMap;
 use std::rc::{Rc, Weak};
 
 use anyhow::{Context, Result};
-use egui::{FontFamily, FontId, TextStyle, Window};
+use egui::{
+    Align2,
+    Area,
+    Color32,
+    ComboBox,
+    FontFamily,
+    FontId,
+    LayerId,
+    Pos2,
+    Rect,
+    Slider,
+    Stroke,
+    StrokeKind,
+    TextStyle,
+    Window,
+};
+use egui_wgpu_backend::wgpu::PresentMode;
 use log::Level;
 
 use super::egui_render::EguiRender;
+use super::engine::DirtyRect;
+use super::frame_pacing::PacingMode;
+use super::frame_timing::FrameTiming;
+use super::recorder::RecordingFormat;
+use super::screen_quad::{ScaleMode, UploadMode};
+use super::screenshot::{DepthFormat, ImageFileFormat};
+use super::sdl_wgpu::{SdlWgpu, SdlWgpuConfiguration};
+use super::time_controls::TimeControls;
+use super::timestep::TimestepMode;
+use super::window_mode::{self, WindowMode};
 use super::{App, AppStats};
 
 pub(super) struct Gui<'a> {
-    app:                 Option<Weak<RefCell<App<'a>>>>,
-    egui_render:         Option<EguiRender<'a>>,
-    perf_window_visible: bool,
-    log_window_visible:  bool,
+    app:                   Option<Weak<RefCell<App<'a>>>>,
+    egui_render:           Option<EguiRender<'a>>,
+    perf_window_visible:   bool,
+    log_window_visible:    bool,
+    dirty_overlay_visible: bool,
 }
 
 fn configure_text_styles(ctx: &egui::Context) {
@@ -34,10 +61,11 @@ fn configure_text_styles(ctx: &egui::Context) {
 impl<'a> Gui<'a> {
     pub(super) fn new() -> Self {
         Self {
-            app:                 None,
-            egui_render:         None,
-            perf_window_visible: true,
-            log_window_visible:  false,
+            app:                   None,
+            egui_render:           None,
+            perf_window_visible:   true,
+            log_window_visible:    false,
+            dirty_overlay_visible: false,
         }
     }
 
@@ -56,11 +84,7 @@ impl<'a> Gui<'a> {
         // let engine = app.engine.borrow_mut();
 
         if self.perf_window_visible {
-            Window::new("Performance").show(ctx, |ui| {
-                let AppStats { fps, mean_frame_time, .. } = *app.stats.borrow();
-                ui.label(format!("Mean Frame Time: {:.2} ms", mean_frame_time * 1e3));
-                ui.label(format!("Mean FPS: {fps:.2}"));
-            });
+            Window::new("Performance").show(ctx, |ui| Self::show_perf(ui, &app));
         }
 
         if self.log_window_visible {
@@ -74,14 +98,382 @@ impl<'a> Gui<'a> {
             });
         }
 
+        if self.dirty_overlay_visible {
+            Self::show_dirty_overlay(ctx, &app);
+        }
+
+        Self::show_recording_indicator(ctx, &app);
+
         Window::new("Settings").resizable(false).vscroll(false).show(ctx, |ui| {
             ui.checkbox(&mut self.perf_window_visible, "Show perf");
             ui.checkbox(&mut self.log_window_visible, "Show log");
+            ui.checkbox(&mut self.dirty_overlay_visible, "Show dirty regions");
+
+            let mut sdl_wgpu_cfg = app.cfg.sdl_wgpu_cfg.borrow_mut();
+            ui.checkbox(&mut sdl_wgpu_cfg.track_window_size, "Track window size");
+            ui.label(format!(
+                "Render resolution: {}x{}",
+                sdl_wgpu_cfg.render_width, sdl_wgpu_cfg.render_height
+            ));
+            ComboBox::from_label("Scale mode")
+                .selected_text(sdl_wgpu_cfg.scale_mode.label())
+                .show_ui(ui, |ui| {
+                    for scale_mode in ScaleMode::ALL {
+                        ui.selectable_value(
+                            &mut sdl_wgpu_cfg.scale_mode,
+                            scale_mode,
+                            scale_mode.label(),
+                        );
+                    }
+                });
+
+            ComboBox::from_label("Upload mode")
+                .selected_text(sdl_wgpu_cfg.upload_mode.label())
+                .show_ui(ui, |ui| {
+                    for upload_mode in UploadMode::ALL {
+                        ui.selectable_value(
+                            &mut sdl_wgpu_cfg.upload_mode,
+                            upload_mode,
+                            upload_mode.label(),
+                        );
+                    }
+                });
+
+            Self::show_present_mode(ui, &app.sdl_wgpu.borrow(), &mut sdl_wgpu_cfg.present_mode);
+            Self::show_window_settings(ui, &app.sdl_wgpu.borrow(), &mut sdl_wgpu_cfg);
+
+            let mut engine_cfg = app.cfg.engine_cfg.borrow_mut();
+            ui.checkbox(&mut engine_cfg.render_thread, "Render thread");
+            ui.add_enabled_ui(engine_cfg.render_thread, |ui| {
+                ui.horizontal(|ui| {
+                    ui.radio_value(&mut engine_cfg.render_buffers, 2, "Double buffering");
+                    ui.radio_value(&mut engine_cfg.render_buffers, 3, "Triple buffering");
+                });
+                ui.checkbox(&mut engine_cfg.pipelined, "Pipelined update/render");
+            });
+
+            let mut screenshot_cfg = app.cfg.screenshot_cfg.borrow_mut();
+            ui.horizontal(|ui| {
+                if ui.button("Screenshot (F12)").clicked() {
+                    app.screenshot_pending.set(true);
+                }
+                if ui.button("Window capture (F10)").clicked() {
+                    app.sdl_wgpu.borrow_mut().request_window_capture();
+                }
+                ui.label(format!("to {}", screenshot_cfg.dir.display()));
+            });
+            ComboBox::from_label("Screenshot format")
+                .selected_text(screenshot_cfg.format.label())
+                .show_ui(ui, |ui| {
+                    for format in ImageFileFormat::ALL {
+                        ui.selectable_value(&mut screenshot_cfg.format, format, format.label());
+                    }
+                });
+            ComboBox::from_label("Depth dump")
+                .selected_text(screenshot_cfg.depth.map_or("None", DepthFormat::label))
+                .show_ui(ui, |ui| {
+                    ui.selectable_value(&mut screenshot_cfg.depth, None, "None");
+                    for format in DepthFormat::ALL {
+                        ui.selectable_value(
+                            &mut screenshot_cfg.depth,
+                            Some(format),
+                            format.label(),
+                        );
+                    }
+                });
+
+            Self::show_recording_settings(ui, &app, screenshot_cfg.format);
+
+            let mut time_controls = app.time_controls.borrow_mut();
+            ui.horizontal(|ui| {
+                let pause_label = if time_controls.paused { "Resume (P)" } else { "Pause (P)" };
+                if ui.button(pause_label).clicked() {
+                    time_controls.toggle_pause();
+                }
+                if ui.button("Step (N)").clicked() {
+                    time_controls.step();
+                }
+            });
+            let mut time_scale = time_controls.time_scale;
+            if ui
+                .add(
+                    Slider::new(
+                        &mut time_scale,
+                        TimeControls::MIN_TIME_SCALE..=TimeControls::MAX_TIME_SCALE,
+                    )
+                    .logarithmic(true)
+                    .text("Time scale (-/=)"),
+                )
+                .changed()
+            {
+                time_controls.set_time_scale(time_scale);
+            }
+
+            let mut timing = app.timing.borrow_mut();
+            let FrameTiming { frame_pacer, timestep, .. } = &mut *timing;
+            ComboBox::from_label("Frame pacing").selected_text(frame_pacer.mode.label()).show_ui(
+                ui,
+                |ui| {
+                    for mode in PacingMode::ALL {
+                        ui.selectable_value(&mut frame_pacer.mode, mode, mode.label());
+                    }
+                },
+            );
+
+            ComboBox::from_label("Timestep").selected_text(timestep.mode.label()).show_ui(
+                ui,
+                |ui| {
+                    for mode in TimestepMode::ALL {
+                        ui.selectable_value(&mut timestep.mode, mode, mode.label());
+                    }
+                },
+            );
+
+            let mut dynamic_resolution = app.dynamic_resolution.borrow_mut();
+            ui.checkbox(&mut dynamic_resolution.enabled, "Dynamic resolution");
+            ui.add_enabled_ui(dynamic_resolution.enabled, |ui| {
+                let max_scale = dynamic_resolution.max_scale;
+                ui.add(
+                    Slider::new(&mut dynamic_resolution.min_scale, 0.1..=max_scale)
+                        .text("Min scale"),
+                );
+                let min_scale = dynamic_resolution.min_scale;
+                ui.add(
+                    Slider::new(&mut dynamic_resolution.max_scale, min_scale..=1.0)
+                        .text("Max scale"),
+                );
+            });
         });
 
         Ok(())
     }
 
+    fn show_perf(ui: &mut egui::Ui, app: &App<'_>) {
+        let AppStats { fps, mean_frame_time, mean_work_time, frame_skips } = *app.stats.borrow();
+        ui.label(format!("Mean Frame Time: {:.2} ms", mean_frame_time * 1e3));
+        ui.label(format!("Mean Work Time: {:.2} ms", mean_work_time * 1e3));
+        ui.label(format!("Mean FPS: {fps:.2}"));
+        let pacing_stats = app.timing.borrow().frame_pacer.stats();
+        ui.label(format!(
+            "Pacing Error: {:.3} ms - Oversleep: {:.3} ms",
+            pacing_stats.error_s * 1e3,
+            pacing_stats.oversleep_s * 1e3
+        ));
+        ui.label(format!("Frame Skips: {frame_skips}"));
+        let upload_stats = app.engine.borrow().upload_stats();
+        #[allow(clippy::as_conversions, clippy::cast_precision_loss)]
+        let uploaded_kib = upload_stats.bytes as f64 / 1024.0;
+        ui.label(format!(
+            "Texture Upload: {uploaded_kib:.1} KiB/frame in {:.3} ms",
+            upload_stats.time_s * 1e3
+        ));
+        ui.label(format!("Staging Misses: {}", upload_stats.staging_misses));
+        let surface_errors = app.sdl_wgpu.borrow().surface_errors;
+        ui.label(format!(
+            "Surface Errors: Reconfigured: {} - Timeouts: {} - Other: {}",
+            surface_errors.reconfigured, surface_errors.timeouts, surface_errors.other
+        ));
+        if let Some(stats) = app.engine.borrow().render_thread_stats() {
+            ui.separator();
+            ui.label(format!("Render Thread: {} buffers", stats.num_buffers));
+            ui.label(format!("Latency: {:.2} ms", stats.latency_s * 1e3));
+            ui.label(format!(
+                "Swaps: {} - Dropped: {} - Repeats: {} - Skipped: {}",
+                stats.swaps, stats.dropped, stats.repeats, stats.skipped
+            ));
+        }
+        let sdl_wgpu = app.sdl_wgpu.borrow();
+        ui.label(format!(
+            "Surface: {}x{} px - Display Scale: {:.2}",
+            sdl_wgpu.surface_configuration.width,
+            sdl_wgpu.surface_configuration.height,
+            sdl_wgpu.scale_factor()
+        ));
+        let sdl_wgpu_cfg = app.cfg.sdl_wgpu_cfg.borrow();
+        ui.label(format!(
+            "Render Scale: {:.0}% ({}x{})",
+            app.dynamic_resolution.borrow().scale() * 100.0,
+            sdl_wgpu_cfg.render_width,
+            sdl_wgpu_cfg.render_height
+        ));
+    }
+
+    // The changes are applied by `SdlWgpu::update_window` at the start of the next frame.
+    fn show_window_settings(
+        ui: &mut egui::Ui,
+        sdl_wgpu: &SdlWgpu<'_>,
+        cfg: &mut SdlWgpuConfiguration,
+    ) {
+        ComboBox::from_label("Window mode").selected_text(cfg.window_mode.label()).show_ui(
+            ui,
+            |ui| {
+                for window_mode in WindowMode::ALL {
+                    ui.selectable_value(&mut cfg.window_mode, window_mode, window_mode.label());
+                }
+            },
+        );
+
+        let displays = window_mode::display_names(&sdl_wgpu.video);
+        let display_label = |display: i32| {
+            let name = usize::try_from(display).ok().and_then(|index| displays.get(index));
+            format!("{display}: {}", name.map_or("?", String::as_str))
+        };
+        ComboBox::from_label("Display").selected_text(display_label(cfg.display)).show_ui(
+            ui,
+            |ui| {
+                for display in (0..displays.len()).filter_map(|index| i32::try_from(index).ok()) {
+                    // The display modes differ from one display to another.
+                    if ui
+                        .selectable_value(&mut cfg.display, display, display_label(display))
+                        .changed()
+                    {
+                        cfg.display_mode = None;
+                    }
+                }
+            },
+        );
+
+        ui.add_enabled_ui(cfg.window_mode == WindowMode::Fullscreen, |ui| {
+            ComboBox::from_label("Display mode")
+                .selected_text(window_mode::display_mode_label(cfg.display_mode))
+                .show_ui(ui, |ui| {
+                    ui.selectable_value(
+                        &mut cfg.display_mode,
+                        None,
+                        window_mode::display_mode_label(None),
+                    );
+                    for display_mode in window_mode::display_modes(&sdl_wgpu.video, cfg.display) {
+                        ui.selectable_value(
+                            &mut cfg.display_mode,
+                            Some(display_mode),
+                            window_mode::display_mode_label(Some(display_mode)),
+                        );
+                    }
+                });
+        });
+    }
+
+    // Present modes the surface does not support are disabled.
+    fn show_present_mode(ui: &mut egui::Ui, sdl_wgpu: &SdlWgpu<'_>, selected: &mut PresentMode) {
+        ComboBox::from_label("Present mode")
+            .selected_text(SdlWgpu::present_mode_label(*selected))
+            .show_ui(ui, |ui| {
+                for present_mode in SdlWgpu::PRESENT_MODES {
+                    ui.add_enabled_ui(sdl_wgpu.supports_present_mode(present_mode), |ui| {
+                        ui.selectable_value(
+                            selected,
+                            present_mode,
+                            SdlWgpu::present_mode_label(present_mode),
+                        );
+                    });
+                }
+            });
+    }
+
+    // Recording controls. The settings apply to the next recording, and image sequences use the
+    // screenshot format.
+    fn show_recording_settings(ui: &mut egui::Ui, app: &App<'_>, image_format: ImageFileFormat) {
+        let recording = app.recorder.borrow().is_some();
+        app.cfg.recording_cfg.borrow_mut().image_format = image_format;
+        ui.horizontal(|ui| {
+            let record_label = if recording { "Stop recording (F9)" } else { "Record (F9)" };
+            // Starting a recording reads its configuration, which must not be borrowed here.
+            if ui.button(record_label).clicked() {
+                app.toggle_recording();
+            }
+            ui.label(format!("to {}", app.cfg.recording_cfg.borrow().dir.display()));
+        });
+
+        let mut recording_cfg = app.cfg.recording_cfg.borrow_mut();
+        ui.add_enabled_ui(!recording, |ui| {
+            ComboBox::from_label("Recording format")
+                .selected_text(recording_cfg.format.label())
+                .show_ui(ui, |ui| {
+                    for format in RecordingFormat::ALL {
+                        ui.selectable_value(&mut recording_cfg.format, format, format.label());
+                    }
+                });
+            ui.checkbox(&mut recording_cfg.fixed_timestep, "Fixed recording timestep");
+        });
+    }
+
+    // Shows the elapsed recording time and the number of recorded frames in the top right corner,
+    // with the number of files when the frame size changed during a video recording, or why the
+    // last recording stopped until dismissed.
+    #[allow(clippy::as_conversions, clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+    fn show_recording_indicator(ctx: &egui::Context, app: &App<'_>) {
+        let recorder = app.recorder.borrow();
+        let Some(recorder) = recorder.as_ref() else {
+            let mut recording_error = app.recording_error.borrow_mut();
+            let Some(error) = recording_error.as_ref() else {
+                return;
+            };
+            let mut dismissed = false;
+            Area::new(egui::Id::new("recording_indicator"))
+                .anchor(Align2::RIGHT_TOP, egui::vec2(-8.0, 8.0))
+                .show(ctx, |ui| {
+                    ui.horizontal(|ui| {
+                        ui.colored_label(
+                            Color32::RED,
+                            format!("\u{25a0} Recording stopped: {error}"),
+                        );
+                        dismissed = ui.small_button("Dismiss").clicked();
+                    });
+                });
+            if dismissed {
+                *recording_error = None;
+            }
+            return;
+        };
+
+        let duration_s = recorder.duration_s() as u64;
+        let segments = recorder.segments();
+        Area::new(egui::Id::new("recording_indicator"))
+            .anchor(Align2::RIGHT_TOP, egui::vec2(-8.0, 8.0))
+            .interactable(false)
+            .show(ctx, |ui| {
+                let files =
+                    if segments > 1 { format!(", {segments} files") } else { String::new() };
+                ui.colored_label(
+                    Color32::RED,
+                    format!(
+                        "\u{25cf} REC {:02}:{:02} ({} frames{files})",
+                        duration_s / 60,
+                        duration_s % 60,
+                        recorder.frames()
+                    ),
+                );
+            });
+    }
+
+    // Outlines the frame buffer regions uploaded in the last frame, mapped to the screen.
+    #[allow(clippy::as_conversions, clippy::cast_precision_loss, clippy::arithmetic_side_effects)]
+    fn show_dirty_overlay(ctx: &egui::Context, app: &App<'_>) {
+        let sdl_wgpu = app.sdl_wgpu.borrow();
+        let cfg = app.cfg.sdl_wgpu_cfg.borrow();
+        let surface_size =
+            (sdl_wgpu.surface_configuration.width, sdl_wgpu.surface_configuration.height);
+        let render_size = (cfg.render_width, cfg.render_height);
+
+        let (viewport_x, viewport_y, viewport_width, viewport_height) =
+            cfg.scale_mode.viewport(render_size, surface_size);
+        let points_per_pixel = 1.0 / ctx.pixels_per_point();
+        let scale_x = viewport_width as f32 / render_size.0 as f32 * points_per_pixel;
+        let scale_y = viewport_height as f32 / render_size.1 as f32 * points_per_pixel;
+        let origin =
+            Pos2::new(viewport_x as f32 * points_per_pixel, viewport_y as f32 * points_per_pixel);
+
+        let painter = ctx.layer_painter(LayerId::background());
+
+        for &DirtyRect { x, y, width, height } in app.engine.borrow().dirty_rects() {
+            let min = origin + egui::vec2(x as f32 * scale_x, y as f32 * scale_y);
+            let size = egui::vec2(width as f32 * scale_x, height as f32 * scale_y);
+            let rect = Rect::from_min_size(min, size);
+            painter.rect_filled(rect, 0.0, Color32::from_rgba_unmultiplied(255, 0, 0, 32));
+            painter.rect_stroke(rect, 0.0, Stroke::new(1.0, Color32::RED), StrokeKind::Inside);
+        }
+    }
+
     pub(super) fn render(&mut self) -> Result<()> {
         let egui_render = self.egui_render.as_mut().context("EguiRender not initialized")?;
         egui_render.render()
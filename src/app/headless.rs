@@ -0,0 +1,270 @@
+use std::cell::RefCell;
+use std::fs;
+use std::path::PathBuf;
+use std::rc::Rc;
+
+use anyhow::{Context, Result, ensure};
+use derive_builder::Builder;
+
+use super::clock::{Clock, StdClock};
+use super::egui_software::EguiSoftwareRender;
+use super::engine::{CameraPath, Engine, EngineConfiguration, Scene};
+use super::golden::{self, GoldenConfiguration};
+use super::scale_size;
+use super::screenshot::{self, ImageFileFormat};
+use super::terminal::{TerminalMode, TerminalPresenter};
+
+/// Configuration of a run without a window or a GPU.
+pub(crate) struct HeadlessConfiguration {
+    engine_cfg:    Rc<RefCell<EngineConfiguration>>,
+    width:         u32,
+    height:        u32,
+    /// Simulated time between two frames, in seconds.
+    dt:            f32,
+    /// File of the scene, the demo scene when not set.
+    scene:         Option<PathBuf>,
+    /// File of the scripted camera path, if any.
+    camera_path:   Option<PathBuf>,
+    /// Number of frames to render.
+    frames:        u32,
+    /// Directory receiving the last `output_frames` frames, if any.
+    output_dir:    Option<PathBuf>,
+    output_frames: u32,
+    output_format: ImageFileFormat,
+    /// Draw the frames in the terminal, if set.
+    terminal:      Option<TerminalMode>,
+    /// Terminal size in characters, detected when not set.
+    terminal_size: (Option<u32>, Option<u32>),
+    /// Draw a GUI window with the progress of the run over the frames.
+    gui_overlay:   bool,
+    /// Compare the last frame with a reference image, if any.
+    golden:        Option<GoldenConfiguration>,
+}
+
+/// Options of a run without a window or a GPU as given on the command line, from which its
+/// configuration is derived.
+#[derive(Builder)]
+#[builder(pattern = "owned")]
+#[allow(clippy::struct_excessive_bools)]
+pub(crate) struct HeadlessOptions {
+    /// Window size the render size is derived from, as with a window.
+    width:                       u32,
+    height:                      u32,
+    /// Internal render size, the window size scaled by `render_scale` when not set.
+    #[builder(default)]
+    render_width:                Option<u32>,
+    #[builder(default)]
+    render_height:               Option<u32>,
+    render_scale:                f32,
+    target_fps:                  u32,
+    /// Simulated time between two frames, one frame at `target_fps` when not set.
+    #[builder(default)]
+    dt:                          Option<f32>,
+    #[builder(default)]
+    scene:                       Option<PathBuf>,
+    #[builder(default)]
+    camera_path:                 Option<PathBuf>,
+    #[builder(default)]
+    render_thread:               bool,
+    frames:                      u32,
+    #[builder(default)]
+    output_dir:                  Option<PathBuf>,
+    output_frames:               u32,
+    output_format:               ImageFileFormat,
+    #[builder(default)]
+    terminal:                    Option<TerminalMode>,
+    #[builder(default)]
+    terminal_columns:            Option<u32>,
+    #[builder(default)]
+    terminal_rows:               Option<u32>,
+    #[builder(default)]
+    gui_overlay:                 bool,
+    /// Reference image to compare the last frame with, if any.
+    #[builder(default)]
+    golden_reference:            Option<PathBuf>,
+    golden_tolerance:            u8,
+    golden_max_differing_pixels: u64,
+    #[builder(default)]
+    update_golden:               bool,
+}
+
+impl HeadlessConfiguration {
+    pub(crate) fn new(options: HeadlessOptions) -> Self {
+        let HeadlessOptions {
+            width,
+            height,
+            render_width,
+            render_height,
+            render_scale,
+            target_fps,
+            dt,
+            scene,
+            camera_path,
+            render_thread,
+            frames,
+            output_dir,
+            output_frames,
+            output_format,
+            terminal,
+            terminal_columns,
+            terminal_rows,
+            gui_overlay,
+            golden_reference,
+            golden_tolerance,
+            golden_max_differing_pixels,
+            update_golden,
+        } = options;
+
+        // Each frame must be complete when it is captured, written or compared, which the render
+        // thread does not guarantee as it presents the last frame completed in the background.
+        if render_thread {
+            log::warn!("The render thread is not used headless, frames are drawn synchronously");
+        }
+        let engine_cfg = Rc::new(RefCell::new(EngineConfiguration {
+            render_thread:  false,
+            pipelined:      false,
+            render_buffers: 2,
+        }));
+
+        let golden = golden_reference.map(|reference| GoldenConfiguration {
+            reference,
+            tolerance: golden_tolerance,
+            max_differing_pixels: golden_max_differing_pixels,
+            update: update_golden,
+        });
+
+        HeadlessConfiguration {
+            engine_cfg,
+            width: render_width.unwrap_or_else(|| scale_size(width, render_scale)),
+            height: render_height.unwrap_or_else(|| scale_size(height, render_scale)),
+            #[allow(clippy::as_conversions, clippy::cast_precision_loss)]
+            dt: dt.unwrap_or(1.0 / target_fps as f32),
+            scene,
+            camera_path,
+            frames,
+            output_dir,
+            output_frames,
+            output_format,
+            terminal,
+            terminal_size: (terminal_columns, terminal_rows),
+            gui_overlay,
+            golden,
+        }
+    }
+}
+
+/// Runs the engine for a fixed number of frames, advancing the world by a fixed time step per
+/// frame, one frame at the target frame rate by default, and rendering into the frame buffer only.
+/// Neither SDL nor wgpu is initialized, so that rendering runs on machines without a display or a
+/// GPU. Fails when the last frame does not match the reference image, if one is configured.
+pub(crate) fn start(cfg: &HeadlessConfiguration) -> Result<()> {
+    ensure!(cfg.dt.is_finite() && cfg.dt > 0.0, "Invalid time step {}", cfg.dt);
+
+    let mut engine = Engine::new_headless(cfg.engine_cfg.clone(), cfg.width, cfg.height)?;
+    if let Some(path) = &cfg.scene {
+        engine.set_scene(Scene::load(path)?);
+    }
+    if let Some(path) = &cfg.camera_path {
+        engine.set_camera_path(CameraPath::load(path)?);
+    }
+
+    if let Some(dir) = &cfg.output_dir {
+        fs::create_dir_all(dir)
+            .with_context(|| format!("Failed to create directory {}", dir.display()))?;
+    }
+    let first_output_frame = cfg.frames.saturating_sub(cfg.output_frames);
+
+    let (terminal_columns, terminal_rows) = cfg.terminal_size;
+    let mut terminal =
+        cfg.terminal.map(|mode| TerminalPresenter::new(mode, terminal_columns, terminal_rows));
+
+    let mut overlay =
+        cfg.gui_overlay.then(|| (egui::Context::default(), EguiSoftwareRender::new()));
+
+    log::info!("Rendering {} frames at {}x{} headless", cfg.frames, cfg.width, cfg.height);
+
+    let clock = StdClock::new();
+    for frame in 0..cfg.frames {
+        engine.update(cfg.dt)?;
+        engine.render(1.0)?;
+
+        if let Some((ctx, egui_render)) = overlay.as_mut() {
+            draw_overlay(ctx, egui_render, &mut engine, frame, cfg.frames, cfg.dt)?;
+        }
+
+        if frame >= first_output_frame
+            && let Some(dir) = &cfg.output_dir
+        {
+            let path =
+                dir.join(format!("frame-{frame:06}")).with_extension(cfg.output_format.extension());
+            screenshot::write_color(&path, cfg.output_format, &engine.capture(false))?;
+        }
+
+        if let Some(terminal) = terminal.as_mut() {
+            terminal.present(&engine.capture(false)).context("Failed to draw in the terminal")?;
+        }
+    }
+    // Restore the terminal before logging the results.
+    drop(terminal);
+
+    let elapsed_s = clock.now_s();
+    log::info!(
+        "Rendered {} frames in {elapsed_s:.2} s ({:.2} ms per frame)",
+        cfg.frames,
+        elapsed_s * 1e3 / f64::from(cfg.frames.max(1))
+    );
+    if let Some(dir) = &cfg.output_dir {
+        log::info!(
+            "Wrote the last {} frames to {}",
+            cfg.output_frames.min(cfg.frames),
+            dir.display()
+        );
+    }
+
+    if let Some(golden_cfg) = &cfg.golden {
+        golden::check(golden_cfg, &engine.capture(false))?;
+    }
+
+    Ok(())
+}
+
+// Draws a GUI window with the progress of the run over the frame, rasterized in software. It only
+// shows simulated values, so that the frames stay the same from one run to another.
+#[allow(clippy::as_conversions, clippy::cast_precision_loss)]
+fn draw_overlay(
+    ctx: &egui::Context,
+    egui_render: &mut EguiSoftwareRender,
+    engine: &mut Engine<'_>,
+    frame: u32,
+    frames: u32,
+    dt: f32,
+) -> Result<()> {
+    let (width, height, color) = engine.frame_mut();
+    let rendered_frames = frame.saturating_add(1);
+    let simulated_s = f64::from(rendered_frames) * f64::from(dt);
+
+    let raw_input = egui::RawInput {
+        screen_rect: Some(egui::Rect::from_min_size(
+            egui::Pos2::ZERO,
+            egui::vec2(width as f32, height as f32),
+        )),
+        time: Some(simulated_s),
+        ..Default::default()
+    };
+
+    let full_output = ctx.run(raw_input, |ctx| {
+        egui::Window::new("Headless").show(ctx, |ui| {
+            ui.label(format!("Frame: {rendered_frames} / {frames}"));
+            ui.label(format!("Simulated time: {simulated_s:.2} s"));
+            ui.label(format!("Time step: {:.2} ms", f64::from(dt) * 1e3));
+            ui.label(format!("Resolution: {width}x{height}"));
+        });
+    });
+
+    egui_render.update_textures(&full_output.textures_delta)?;
+    let primitives = ctx.tessellate(full_output.shapes, full_output.pixels_per_point);
+    egui_render.paint(&primitives, full_output.pixels_per_point, color, width, height);
+    egui_render.free_textures(&full_output.textures_delta);
+
+    Ok(())
+}
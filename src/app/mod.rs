@@ -1,39 +1,79 @@
-use std::cell::RefCell;
+use std::cell::{Cell, RefCell};
+use std::path::PathBuf;
 use std::rc::Rc;
-use std::{thread, time};
 
 use anyhow::{Result, anyhow};
+use derive_builder::Builder;
 use egui_sdl2_platform::sdl2::EventPump;
 use egui_sdl2_platform::{Platform, sdl2};
+use egui_wgpu_backend::wgpu::PresentMode;
 use enum_map::{Enum, EnumMap, enum_map};
 use sdl2::event::{Event, WindowEvent};
 use sdl2::keyboard::Keycode;
-use time::Duration;
 
+mod clock;
 pub(crate) mod constants;
+mod dynamic_resolution;
 mod egui_render;
+mod egui_software;
 mod engine;
 mod frame_history;
+mod frame_pacing;
+mod frame_timing;
+mod golden;
 mod gui;
+pub(crate) mod headless;
 mod input_action;
 mod input_manager;
 pub(crate) mod log_utils;
+mod recorder;
 mod screen_quad;
+mod screenshot;
+mod sdl_canvas;
 mod sdl_wgpu;
+mod staging_ring;
 mod terminal;
-
+mod time_controls;
+mod timestep;
+mod window_capture;
+mod window_mode;
+
+use clock::{Clock, ManualClock, SdlClock};
+use constants::{HEIGHT, MAX_DYNAMIC_RENDER_SCALE, MIN_DYNAMIC_RENDER_SCALE, WIDTH};
+use dynamic_resolution::DynamicResolution;
 use egui_render::EguiRender;
 use engine::{Engine, EngineConfiguration};
-use frame_history::FrameHistory;
+pub(crate) use frame_pacing::PacingMode;
+use frame_timing::FrameTiming;
 use gui::Gui;
-use input_action::{InputAction, InputActionBuilder};
+pub(crate) use headless::{HeadlessConfiguration, HeadlessOptionsBuilder};
+use input_action::{InputAction, InputActionBehavior, InputActionBuilder};
 use input_manager::InputManager;
-use screen_quad::ScreenQuad;
+pub(crate) use recorder::RecordingFormat;
+use recorder::{Recorder, RecordingConfiguration};
+pub(crate) use screen_quad::ScaleMode;
+use screen_quad::{ScreenQuad, UploadMode};
+use screenshot::ScreenshotConfiguration;
+pub(crate) use screenshot::{DepthFormat, ImageFileFormat};
+pub(crate) use sdl_canvas::Presenter;
+pub(crate) use sdl_wgpu::{AdapterPreference, GraphicsBackend};
 use sdl_wgpu::{SdlWgpu, SdlWgpuConfiguration};
+pub(crate) use terminal::TerminalMode;
+use time_controls::TimeControls;
+pub(crate) use timestep::TimestepMode;
+use window_mode::WindowGeometry;
+pub(crate) use window_mode::WindowMode;
 
 #[derive(Copy, Clone, Debug, Enum)]
 enum InputActionType {
     ActionA,
+    TogglePause,
+    StepFrame,
+    SlowDown,
+    SpeedUp,
+    Screenshot,
+    WindowCapture,
+    ToggleRecording,
     // MoveForward,
     // MoveBackward,
     // MoveLeft,
@@ -49,45 +89,247 @@ enum InputActionType {
 type InputActionMap = EnumMap<InputActionType, Rc<RefCell<InputAction>>>;
 
 pub(crate) struct AppConfiguration {
-    sdl_wgpu_cfg: Rc<RefCell<SdlWgpuConfiguration>>,
-    engine_cfg:   Rc<RefCell<EngineConfiguration>>,
-    target_fps:   i32,
+    presenter:          Presenter,
+    sdl_wgpu_cfg:       Rc<RefCell<SdlWgpuConfiguration>>,
+    engine_cfg:         Rc<RefCell<EngineConfiguration>>,
+    screenshot_cfg:     RefCell<ScreenshotConfiguration>,
+    recording_cfg:      RefCell<RecordingConfiguration>,
+    /// Start recording at launch.
+    record:             bool,
+    /// File keeping the windowed position and size of the window across runs.
+    window_state:       PathBuf,
+    /// Internal render size given explicitly, instead of derived from the window size.
+    render_size:        (Option<u32>, Option<u32>),
+    target_fps:         u32,
+    pacing_mode:        PacingMode,
+    timestep_mode:      TimestepMode,
+    dynamic_resolution: bool,
+    virtual_clock:      bool,
+}
+
+/// Options of the application as given on the command line, from which its configuration is
+/// derived.
+#[derive(Builder)]
+#[builder(pattern = "owned")]
+#[allow(clippy::struct_excessive_bools)]
+pub(crate) struct AppOptions {
+    title:                    &'static str,
+    presenter:                Presenter,
+    /// Window size, the size of the last run when not set.
+    #[builder(default)]
+    width:                    Option<u32>,
+    #[builder(default)]
+    height:                   Option<u32>,
+    /// Internal render size, the window size scaled by `render_scale` when not set.
+    #[builder(default)]
+    render_width:             Option<u32>,
+    #[builder(default)]
+    render_height:            Option<u32>,
+    render_scale:             f32,
+    scale_mode:               ScaleMode,
+    #[builder(default)]
+    track_window_size:        bool,
+    window_mode:              WindowMode,
+    /// Display to open the window on, the one of the last run when not set.
+    #[builder(default)]
+    display:                  Option<i32>,
+    window_state:             PathBuf,
+    vsync:                    bool,
+    backend:                  GraphicsBackend,
+    adapter_preference:       AdapterPreference,
+    #[builder(default)]
+    force_fallback_adapter:   bool,
+    target_fps:               u32,
+    /// Frame pacing, derived from the present mode when not set.
+    #[builder(default)]
+    pacing_mode:              Option<PacingMode>,
+    timestep_mode:            TimestepMode,
+    #[builder(default)]
+    dynamic_resolution:       bool,
+    #[builder(default)]
+    render_thread:            bool,
+    render_buffers:           usize,
+    #[builder(default)]
+    pipelined:                bool,
+    #[builder(default)]
+    virtual_clock:            bool,
+    screenshot_dir:           PathBuf,
+    screenshot_format:        ImageFileFormat,
+    #[builder(default)]
+    screenshot_depth:         Option<DepthFormat>,
+    #[builder(default)]
+    record:                   bool,
+    recording_dir:            PathBuf,
+    recording_format:         RecordingFormat,
+    #[builder(default)]
+    recording_fixed_timestep: bool,
+}
+
+impl AppConfiguration {
+    pub(crate) fn new(options: AppOptions) -> Self {
+        let AppOptions {
+            title,
+            presenter,
+            width,
+            height,
+            render_width,
+            render_height,
+            render_scale,
+            scale_mode,
+            track_window_size,
+            window_mode,
+            display,
+            window_state,
+            vsync,
+            backend,
+            adapter_preference,
+            force_fallback_adapter,
+            target_fps,
+            pacing_mode,
+            timestep_mode,
+            dynamic_resolution,
+            render_thread,
+            render_buffers,
+            pipelined,
+            virtual_clock,
+            screenshot_dir,
+            screenshot_format,
+            screenshot_depth,
+            record,
+            recording_dir,
+            recording_format,
+            recording_fixed_timestep,
+        } = options;
+
+        // The window reopens where it was left, unless placed on a display or sized explicitly.
+        let saved_geometry = WindowGeometry::load(&window_state).unwrap_or_else(|err| {
+            log::warn!("{err:#}");
+            None
+        });
+        let width = width.or(saved_geometry.map(|geometry| geometry.width)).unwrap_or(WIDTH);
+        let height = height.or(saved_geometry.map(|geometry| geometry.height)).unwrap_or(HEIGHT);
+        let position =
+            saved_geometry.filter(|_| display.is_none()).map(|WindowGeometry { x, y, .. }| (x, y));
+
+        let present_mode = if vsync { PresentMode::Fifo } else { PresentMode::Immediate };
+        let sdl_wgpu_cfg = Rc::new(RefCell::new(SdlWgpuConfiguration {
+            title,
+            width,
+            height,
+            render_width: render_width.unwrap_or_else(|| scale_size(width, render_scale)),
+            render_height: render_height.unwrap_or_else(|| scale_size(height, render_scale)),
+            render_scale,
+            scale_mode,
+            track_window_size,
+            upload_mode: UploadMode::Staging,
+            window_mode,
+            display: display.unwrap_or_default(),
+            display_mode: None,
+            position,
+            vsync,
+            present_mode,
+            backend,
+            adapter_preference,
+            force_fallback_adapter,
+        }));
+
+        let engine_cfg =
+            Rc::new(RefCell::new(EngineConfiguration { render_thread, pipelined, render_buffers }));
+
+        // With vsync, the blocking present paces the frames unless another mode is requested. The
+        // virtual clock only advances through the waits of the fixed FPS mode, and conflicts with
+        // an explicit mode.
+        let pacing_mode = match pacing_mode {
+            Some(pacing_mode) => pacing_mode,
+            None if virtual_clock => PacingMode::FixedFps,
+            None => PacingMode::default_for(present_mode),
+        };
+
+        let screenshot_cfg = RefCell::new(ScreenshotConfiguration {
+            dir:    screenshot_dir,
+            format: screenshot_format,
+            depth:  screenshot_depth,
+        });
+
+        // Image sequences share the file format of the screenshots.
+        let recording_cfg = RefCell::new(RecordingConfiguration {
+            dir:            recording_dir,
+            format:         recording_format,
+            image_format:   screenshot_format,
+            fixed_timestep: recording_fixed_timestep,
+        });
+
+        AppConfiguration {
+            presenter,
+            sdl_wgpu_cfg,
+            engine_cfg,
+            screenshot_cfg,
+            recording_cfg,
+            record,
+            window_state,
+            render_size: (render_width, render_height),
+            target_fps,
+            pacing_mode,
+            timestep_mode,
+            dynamic_resolution,
+            virtual_clock,
+        }
+    }
 }
 
 impl AppConfiguration {
-    pub(crate) fn new(
-        title: &'static str,
-        width: u32,
-        height: u32,
-        fullscreen: bool,
-        vsync: bool,
-        target_fps: i32,
-    ) -> Self {
-        let sdl_wgpu_cfg =
-            Rc::new(RefCell::new(SdlWgpuConfiguration { title, width, height, fullscreen, vsync }));
-
-        let engine_cfg = Rc::new(RefCell::new(EngineConfiguration {}));
-
-        AppConfiguration { sdl_wgpu_cfg, engine_cfg, target_fps }
+    /// Derives the internal render size from the drawable size of the window, in pixels, as the
+    /// window size is in points on high density displays. Explicit sizes are kept.
+    fn fit_render_size(&self, (drawable_width, drawable_height): (u32, u32)) {
+        let mut sdl_wgpu_cfg = self.sdl_wgpu_cfg.borrow_mut();
+        let render_scale = sdl_wgpu_cfg.render_scale;
+        let (render_width, render_height) = self.render_size;
+        sdl_wgpu_cfg.render_width =
+            render_width.unwrap_or_else(|| scale_size(drawable_width, render_scale));
+        sdl_wgpu_cfg.render_height =
+            render_height.unwrap_or_else(|| scale_size(drawable_height, render_scale));
     }
 }
 
+/// Scales a window dimension to the matching internal render dimension.
+#[allow(clippy::as_conversions, clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+fn scale_size(size: u32, scale: f32) -> u32 {
+    (f64::from(size) * f64::from(scale)).round().max(1.0) as u32
+}
+
 struct AppStats {
-    frame_history:   FrameHistory,
     mean_frame_time: f32,
+    mean_work_time:  f32,
     fps:             f32,
+    /// Simulation steps run in addition to the one of each frame, to catch up with real time.
+    frame_skips:     u64,
 }
 
 pub(crate) struct App<'a> {
-    cfg:             AppConfiguration,
-    sdl_wgpu:        Rc<RefCell<SdlWgpu<'a>>>,
-    platform:        Rc<RefCell<Platform>>,
-    engine:          Rc<RefCell<Engine<'a>>>,
-    gui:             RefCell<Gui<'a>>,
-    input_actions:   InputActionMap,
-    input_manager:   RefCell<InputManager>,
-    stats:           RefCell<AppStats>,
-    time_multiplier: f32,
+    cfg:                AppConfiguration,
+    clock:              Rc<dyn Clock>,
+    sdl_wgpu:           Rc<RefCell<SdlWgpu<'a>>>,
+    platform:           Rc<RefCell<Platform>>,
+    engine:             Rc<RefCell<Engine<'a>>>,
+    gui:                RefCell<Gui<'a>>,
+    input_actions:      InputActionMap,
+    input_manager:      RefCell<InputManager>,
+    stats:              RefCell<AppStats>,
+    dynamic_resolution: RefCell<DynamicResolution>,
+    timing:             RefCell<FrameTiming>,
+    /// Present mode of the surface the frame pacing was last adapted to.
+    present_mode:       Cell<PresentMode>,
+    /// Render resolution selected at startup, scaled by the dynamic resolution controller when
+    /// the window size is not tracked.
+    render_base_size:   (u32, u32),
+    time_controls:      RefCell<TimeControls>,
+    /// Set to save a screenshot of the next rendered frame.
+    screenshot_pending: Cell<bool>,
+    /// The recording in progress, if any.
+    recorder:           RefCell<Option<Recorder>>,
+    /// Why the last recording stopped on its own or failed to start, shown until dismissed or
+    /// until the next recording.
+    recording_error:    RefCell<Option<String>>,
 }
 
 pub(crate) enum EventOutcome {
@@ -96,17 +338,15 @@ pub(crate) enum EventOutcome {
 }
 
 impl App<'_> {
-    const MAX_FRAME_SKIPS: u32 = 5;
-    const NUM_DELAYS_PER_YIELD: u32 = 16;
-
     pub(crate) fn new(cfg: AppConfiguration) -> Result<Rc<RefCell<Self>>> {
         let sdl_wgpu = Rc::new(RefCell::new(SdlWgpu::new(cfg.sdl_wgpu_cfg.clone())?));
+        cfg.fit_render_size(sdl_wgpu.borrow().window.drawable_size());
 
         let platform = Rc::new(RefCell::new(Platform::new(sdl_wgpu.borrow().window.size())?));
 
         let egui_render = EguiRender::new(platform.clone(), sdl_wgpu.clone());
 
-        let screen_quad = ScreenQuad::new(sdl_wgpu.clone());
+        let screen_quad = ScreenQuad::new(sdl_wgpu.clone())?;
 
         log_utils::clear_logs();
 
@@ -116,22 +356,54 @@ impl App<'_> {
 
         let gui = Gui::new();
 
-        #[allow(clippy::as_conversions, clippy::cast_precision_loss)]
-        let frame_history = FrameHistory::new(300, 1.0);
+        let clock = Self::create_clock(&cfg);
+
+        let stats = RefCell::new(AppStats {
+            mean_frame_time: 0.,
+            mean_work_time:  0.,
+            fps:             0.,
+            frame_skips:     0,
+        });
+
+        let dynamic_resolution = RefCell::new(DynamicResolution::new(
+            cfg.dynamic_resolution,
+            MIN_DYNAMIC_RENDER_SCALE,
+            MAX_DYNAMIC_RENDER_SCALE,
+        ));
+
+        let timing = RefCell::new(FrameTiming::new(&cfg, clock.clone()));
+        let present_mode = sdl_wgpu.borrow().surface_configuration.present_mode;
+
+        // Recordings play back at the target frame rate.
+        let recorder = if cfg.record {
+            Some(Recorder::start(&cfg.recording_cfg.borrow(), cfg.target_fps)?)
+        } else {
+            None
+        };
 
-        let stats = RefCell::new(AppStats { frame_history, mean_frame_time: 0., fps: 0. });
+        let render_base_size = {
+            let sdl_wgpu_cfg = cfg.sdl_wgpu_cfg.borrow();
+            (sdl_wgpu_cfg.render_width, sdl_wgpu_cfg.render_height)
+        };
 
         let app = Rc::new(RefCell::new(App {
             cfg,
+            clock,
             sdl_wgpu,
             platform,
             engine,
             gui: RefCell::new(gui),
             input_actions,
             input_manager: RefCell::new(input_manager),
-            #[allow(clippy::cast_precision_loss, clippy::as_conversions)]
             stats,
-            time_multiplier: 1.0,
+            dynamic_resolution,
+            timing,
+            present_mode: Cell::new(present_mode),
+            render_base_size,
+            time_controls: RefCell::new(TimeControls::new()),
+            screenshot_pending: Cell::new(false),
+            recorder: RefCell::new(recorder),
+            recording_error: RefCell::new(None),
         }));
 
         app.borrow().gui.borrow_mut().init_gui(&app, egui_render);
@@ -142,12 +414,28 @@ impl App<'_> {
         Ok(app)
     }
 
+    fn create_clock(cfg: &AppConfiguration) -> Rc<dyn Clock> {
+        if cfg.virtual_clock {
+            log::info!("Using a virtual clock at {} FPS", cfg.target_fps);
+            Rc::new(ManualClock::new(SdlClock.frequency()))
+        } else {
+            Rc::new(SdlClock)
+        }
+    }
+
     fn init_input() -> Result<(InputActionMap, InputManager)> {
         let input_actions_map = Self::init_input_actions()?;
 
         let mut input_manager = InputManager::new();
 
         input_manager.map_to_key(Keycode::A, &input_actions_map[InputActionType::ActionA]);
+        input_manager.map_to_key(Keycode::P, &input_actions_map[InputActionType::TogglePause]);
+        input_manager.map_to_key(Keycode::N, &input_actions_map[InputActionType::StepFrame]);
+        input_manager.map_to_key(Keycode::Minus, &input_actions_map[InputActionType::SlowDown]);
+        input_manager.map_to_key(Keycode::Equals, &input_actions_map[InputActionType::SpeedUp]);
+        input_manager.map_to_key(Keycode::F12, &input_actions_map[InputActionType::Screenshot]);
+        input_manager.map_to_key(Keycode::F10, &input_actions_map[InputActionType::WindowCapture]);
+        input_manager.map_to_key(Keycode::F9, &input_actions_map[InputActionType::ToggleRecording]);
 
         Ok((input_actions_map, input_manager))
     }
@@ -162,31 +450,54 @@ impl App<'_> {
                 .map_err(|_err| anyhow!("Failed to build input action"))?,
         ));
 
+        // Time controls and screenshots act once per key press.
+        let mut build_once_action = |name: &str| -> Result<Rc<RefCell<InputAction>>> {
+            Ok(Rc::new(RefCell::new(
+                input_action_builder
+                    .name(name)
+                    .behavior(InputActionBehavior::DetectInitialPressOnly)
+                    .build()
+                    .map_err(|_err| anyhow!("Failed to build input action"))?,
+            )))
+        };
+
+        let toggle_pause = build_once_action("togglePause")?;
+        let step_frame = build_once_action("stepFrame")?;
+        let slow_down = build_once_action("slowDown")?;
+        let speed_up = build_once_action("speedUp")?;
+        let screenshot = build_once_action("screenshot")?;
+        let window_capture = build_once_action("windowCapture")?;
+        let toggle_recording = build_once_action("toggleRecording")?;
+
         #[allow(clippy::mem_forget)]
         Ok(enum_map! {
             InputActionType::ActionA => action_a.clone(),
+            InputActionType::TogglePause => toggle_pause.clone(),
+            InputActionType::StepFrame => step_frame.clone(),
+            InputActionType::SlowDown => slow_down.clone(),
+            InputActionType::SpeedUp => speed_up.clone(),
+            InputActionType::Screenshot => screenshot.clone(),
+            InputActionType::WindowCapture => window_capture.clone(),
+            InputActionType::ToggleRecording => toggle_recording.clone(),
         })
     }
 
-    #[allow(unsafe_code)]
-    fn get_performance_counter() -> u64 {
-        unsafe { sdl2::sys::SDL_GetPerformanceCounter() }
-    }
-
-    #[allow(unsafe_code)]
-    fn get_performance_frequency() -> u64 {
-        unsafe { sdl2::sys::SDL_GetPerformanceFrequency() }
-    }
-
-    fn update(&self, frame_time_s: f32) -> Result<()> {
-        let dt = frame_time_s * self.time_multiplier;
+    fn update(&self, dt: f32) -> Result<()> {
         self.process_input_actions(dt);
         self.engine.borrow_mut().update(dt)?;
         Ok(())
     }
 
     pub(crate) fn start(cfg: AppConfiguration) -> Result<()> {
-        App::new(cfg)?.borrow().run()
+        match cfg.presenter {
+            Presenter::Wgpu => App::new(cfg)?.borrow().run(),
+            Presenter::Sdl => sdl_canvas::run(&cfg),
+        }
+    }
+
+    /// Prints the wgpu adapters available for the configured backend, without starting.
+    pub(crate) fn list_adapters(cfg: &AppConfiguration) -> Result<()> {
+        SdlWgpu::list_adapters(&cfg.sdl_wgpu_cfg.borrow())
     }
 
     #[allow(
@@ -204,58 +515,66 @@ impl App<'_> {
             .event_pump()
             .map_err(|e| anyhow!("Failed to get sdl event pump: {}", e))?;
 
-        let perf_frequency = Self::get_performance_frequency() as f64;
-        let frame_ticks = perf_frequency / f64::from(self.cfg.target_fps);
-        let start_ticks = Self::get_performance_counter();
+        let clock = self.clock.as_ref();
+        let perf_frequency = clock.frequency() as f64;
+        let frame_s = self.timing.borrow().frame_s();
+        let start_ticks = clock.now();
         let stats_update_interval = perf_frequency as u64 / 4;
         let mut last_stats_update = start_ticks;
         let mut before_ticks = start_ticks;
-        let mut over_sleep_ticks = 0_f64;
-        let mut num_delays = 0_u32;
-        let mut excess_ticks = 0_f64;
-        let mut end_ticks: u64;
-        let mut frame_skips = 0_u32;
-
-        let tick_to_sec = |ticks: f64| -> f64 { ticks / perf_frequency };
-        let tick_to_msec = |ticks: f64| -> f64 { tick_to_sec(ticks) * 1e3 };
-
-        let mut update = || {
-            let mut update_stats = || {
-                let mut stats = self.stats.borrow_mut();
-                let now = Self::get_performance_counter();
-                if now - last_stats_update >= stats_update_interval {
-                    stats.mean_frame_time = stats.frame_history.mean_frame_time();
-                    stats.fps = stats.frame_history.fps();
-                    last_stats_update = now;
-                }
-                self.sdl_wgpu.borrow_mut().set_window_title(
-                    format!(
-                        "{} - FPS: {:.2} - Mean frame time: {:.2} ms",
-                        self.cfg.sdl_wgpu_cfg.borrow().title,
-                        stats.fps,
-                        stats.mean_frame_time * 1e3
-                    )
-                    .as_str(),
-                );
-            };
-
-            update_stats();
-            self.update(tick_to_sec(frame_ticks) as f32)
+        let mut pixels_per_point = None;
+
+        let tick_to_sec = |ticks: f64| -> f64 { clock.to_seconds(ticks) };
+
+        let mut update_stats = || {
+            let mut stats = self.stats.borrow_mut();
+            let now = clock.now();
+            if now - last_stats_update >= stats_update_interval {
+                let frame_history = &self.timing.borrow().frame_history;
+                stats.mean_frame_time = frame_history.mean_frame_time();
+                stats.mean_work_time = frame_history.mean_work_time();
+                stats.fps = frame_history.fps();
+                last_stats_update = now;
+            }
+            self.sdl_wgpu.borrow_mut().set_window_title(
+                format!(
+                    "{} - FPS: {:.2} - Mean frame time: {:.2} ms",
+                    self.cfg.sdl_wgpu_cfg.borrow().title,
+                    stats.fps,
+                    stats.mean_frame_time * 1e3
+                )
+                .as_str(),
+            );
         };
 
-        #[allow(clippy::shadow_unrelated)]
-        let update_frame_history = |before_ticks: u64, end_ticks: u64| {
-            let before_time_s = tick_to_sec(before_ticks as f64);
-            let end_time_s = tick_to_sec(end_ticks as f64);
-            let frame_duration_s = (end_time_s - before_time_s) as f32;
-            self.stats.borrow_mut().frame_history.on_new_frame(end_time_s, Some(frame_duration_s));
+        let render_thread_warned = Cell::new(false);
+        let update_dynamic_resolution = |end_ticks: u64| {
+            // The work time of the main loop leaves out the rasterization done on the render
+            // thread, so it says nothing about the cost of the render scale in that mode.
+            if self.cfg.engine_cfg.borrow().render_thread {
+                if self.dynamic_resolution.borrow().enabled && !render_thread_warned.replace(true) {
+                    log::warn!("Dynamic resolution is paused while the render thread is enabled");
+                }
+                return false;
+            }
+            render_thread_warned.set(false);
+
+            let mean_work_time_s = self.timing.borrow().frame_history.mean_work_time();
+            self.dynamic_resolution.borrow_mut().update(
+                tick_to_sec(end_ticks as f64),
+                mean_work_time_s,
+                frame_s as f32,
+            )
         };
 
         'main: loop {
+            self.sdl_wgpu.borrow_mut().update_window();
             if let EventOutcome::Quit = self.handle_events(&mut event_pump) {
                 break 'main;
             }
 
+            self.update_render_size()?;
+
             let App { sdl_wgpu, platform, engine, gui, .. } = self;
 
             {
@@ -267,70 +586,150 @@ impl App<'_> {
                     platform.update_time(elapsed_time_s);
                     platform.context()
                 };
+                // The GUI is laid out in window points and drawn in surface pixels. A change of
+                // density applies from the next frame.
+                let scale_factor = sdl_wgpu.borrow().scale_factor();
+                if pixels_per_point.replace(scale_factor) != Some(scale_factor) {
+                    ctx.set_pixels_per_point(scale_factor);
+                }
 
                 gui.borrow_mut().show_ui(&ctx)?;
             }
 
-            update()?;
+            update_stats();
+            self.process_debug_actions();
+
+            // Fixed steps are interpolated by the renderer from the time left in the accumulator.
+            // While paused, the accumulator is frozen and single steps keep the same alpha.
+            let (steps, alpha) = {
+                let mut time_controls = self.time_controls.borrow_mut();
+                let mut timing = self.timing.borrow_mut();
+                let elapsed_s = timing.elapsed_s(before_ticks);
+                let steps = if time_controls.paused {
+                    for _ in 0..time_controls.take_steps() {
+                        timing.timestep.step(|step_s| self.update(step_s as f32))?;
+                    }
+                    // Single steps do not count as catching up with real time.
+                    0
+                } else {
+                    // A fixed step recording advances by one frame per recorded frame, however
+                    // long the frame actually took.
+                    let elapsed_s = match &*self.recorder.borrow() {
+                        Some(recorder) if recorder.fixed_timestep => frame_s,
+                        _ => elapsed_s,
+                    };
+                    let scaled_s = elapsed_s * f64::from(time_controls.time_scale);
+                    timing.timestep.advance(scaled_s, |step_s| self.update(step_s as f32))?
+                };
+                (steps, timing.timestep.alpha())
+            };
+            {
+                let mut stats = self.stats.borrow_mut();
+                stats.frame_skips =
+                    stats.frame_skips.saturating_add(u64::from(steps.saturating_sub(1)));
+            }
 
-            sdl_wgpu.borrow_mut().init_render()?;
-            engine.borrow_mut().render()?;
+            // Time spent waiting for the surface texture is not part of the work time. Running
+            // out of memory ends the loop and shuts down as on quit, saving the window geometry,
+            // the drops finishing an ongoing recording.
+            let init_render_ticks = clock.now();
+            let frame_acquired = match sdl_wgpu.borrow_mut().init_render() {
+                Ok(frame_acquired) => frame_acquired,
+                Err(err) => {
+                    log::error!("Shutting down after a fatal surface error: {err:#}");
+                    break 'main;
+                },
+            };
+            let wait_ticks = clock.now() - init_render_ticks;
+
+            // A vsync'ed present no longer paces the frames after a switch to another mode.
+            let present_mode = sdl_wgpu.borrow().surface_configuration.present_mode;
+            let previous_present_mode = self.present_mode.replace(present_mode);
+            if present_mode != previous_present_mode {
+                let frame_pacer = &mut self.timing.borrow_mut().frame_pacer;
+                frame_pacer.on_present_mode_change(previous_present_mode, present_mode);
+                log::info!("Frame pacing set to {}", frame_pacer.mode.label());
+            }
+
+            // Without a surface texture, the frame is skipped but the GUI still ends its frame.
+            if frame_acquired {
+                engine.borrow_mut().render(alpha)?;
+                if self.screenshot_pending.take() {
+                    let capture = engine.borrow_mut().capture(true);
+                    screenshot::save(&self.cfg.screenshot_cfg.borrow(), "screenshot", capture)?;
+                }
+                let mut recorder = self.recorder.borrow_mut();
+                if let Some(active) = recorder.as_mut()
+                    && let Err(err) = active.record(engine.borrow_mut().capture(false))
+                {
+                    log::error!("Recording stopped: {err:#}");
+                    *self.recording_error.borrow_mut() = Some(format!("{err:#}"));
+                    *recorder = None;
+                }
+            }
             gui.borrow_mut().render()?;
 
+            let work_ticks = clock.now() - before_ticks - wait_ticks;
+
             sdl_wgpu.borrow_mut().present();
+            for capture in sdl_wgpu.borrow_mut().take_window_captures() {
+                screenshot::save(&self.cfg.screenshot_cfg.borrow(), "window", capture)?;
+            }
             gui.borrow_mut().clean()?;
 
-            let after_ticks = Self::get_performance_counter();
+            let after_ticks = clock.now();
 
             {
-                let proc_ticks = (after_ticks - before_ticks) as f64 + over_sleep_ticks;
+                let end_ticks =
+                    self.timing.borrow_mut().end_frame(before_ticks, after_ticks, work_ticks);
 
-                if frame_ticks >= proc_ticks {
-                    let sleep_ticks = frame_ticks - proc_ticks;
-                    let sleep_time_ms = tick_to_msec(sleep_ticks) as u64;
-                    if sleep_time_ms > 0 {
-                        thread::sleep(Duration::from_millis(sleep_time_ms));
-                    }
-                    end_ticks = Self::get_performance_counter();
-                    over_sleep_ticks = (end_ticks - after_ticks) as f64 - sleep_ticks;
-                    if over_sleep_ticks < 0. {
-                        over_sleep_ticks = 0.;
-                        loop {
-                            if (end_ticks - before_ticks) as f64 >= frame_ticks {
-                                break;
-                            }
-                            end_ticks = Self::get_performance_counter();
-                        }
-                    }
-                    num_delays = 0;
-                } else {
-                    num_delays += 1;
-                    if num_delays >= Self::NUM_DELAYS_PER_YIELD {
-                        thread::yield_now();
-                        num_delays = 0;
-                    }
-                    over_sleep_ticks = 0.;
-                    excess_ticks += proc_ticks - frame_ticks;
-                    end_ticks = Self::get_performance_counter();
+                if update_dynamic_resolution(end_ticks) {
+                    self.update_render_size()?;
                 }
 
-                update_frame_history(before_ticks, end_ticks);
-
                 before_ticks = end_ticks;
-
-                let mut skips = 0;
-                while excess_ticks >= frame_ticks && skips < Self::MAX_FRAME_SKIPS {
-                    update()?;
-                    excess_ticks -= frame_ticks;
-                    skips += 1;
-                }
-                frame_skips += skips;
             }
         }
 
+        let geometry = self.sdl_wgpu.borrow().windowed_geometry();
+        if let Err(err) = geometry.save(&self.cfg.window_state) {
+            log::error!("{err:#}");
+        }
+
         Ok(())
     }
 
+    /// Resizes the internal render target to follow the surface size when window tracking is
+    /// enabled, and the render scale chosen by the dynamic resolution controller.
+    fn update_render_size(&self) -> Result<()> {
+        let (width, height) = {
+            let sdl_wgpu = self.sdl_wgpu.borrow();
+            let mut cfg = self.cfg.sdl_wgpu_cfg.borrow_mut();
+
+            let (base_width, base_height) = if cfg.track_window_size {
+                (
+                    scale_size(sdl_wgpu.surface_configuration.width, cfg.render_scale),
+                    scale_size(sdl_wgpu.surface_configuration.height, cfg.render_scale),
+                )
+            } else {
+                self.render_base_size
+            };
+
+            let dynamic_scale = self.dynamic_resolution.borrow().scale();
+            let size =
+                (scale_size(base_width, dynamic_scale), scale_size(base_height, dynamic_scale));
+
+            if size == (cfg.render_width, cfg.render_height) {
+                return Ok(());
+            }
+
+            (cfg.render_width, cfg.render_height) = size;
+            size
+        };
+
+        self.engine.borrow_mut().resize(width, height)
+    }
+
     fn get_input_action(&self, input_action_type: InputActionType) -> Rc<RefCell<InputAction>> {
         self.input_actions[input_action_type].clone()
     }
@@ -343,6 +742,56 @@ impl App<'_> {
         }
     }
 
+    fn process_debug_actions(&self) {
+        let mut time_controls = self.time_controls.borrow_mut();
+        let was_pressed = |input_action_type: InputActionType| {
+            self.get_input_action(input_action_type).borrow_mut().get_amount() != 0
+        };
+
+        if was_pressed(InputActionType::TogglePause) {
+            time_controls.toggle_pause();
+        }
+        if was_pressed(InputActionType::StepFrame) {
+            time_controls.step();
+        }
+        if was_pressed(InputActionType::SlowDown) {
+            time_controls.slow_down();
+        }
+        if was_pressed(InputActionType::SpeedUp) {
+            time_controls.speed_up();
+        }
+        if was_pressed(InputActionType::Screenshot) {
+            self.screenshot_pending.set(true);
+        }
+        if was_pressed(InputActionType::WindowCapture) {
+            self.sdl_wgpu.borrow_mut().request_window_capture();
+        }
+        if was_pressed(InputActionType::ToggleRecording) {
+            self.toggle_recording();
+        }
+    }
+
+    /// Starts a new recording, or stops the one in progress.
+    fn toggle_recording(&self) {
+        let mut recorder = self.recorder.borrow_mut();
+        // Dropping the recorder waits for the queued frames to be written.
+        if recorder.take().is_some() {
+            return;
+        }
+
+        let mut recording_error = self.recording_error.borrow_mut();
+        match Recorder::start(&self.cfg.recording_cfg.borrow(), self.cfg.target_fps) {
+            Ok(started) => {
+                *recorder = Some(started);
+                *recording_error = None;
+            },
+            Err(err) => {
+                log::error!("Failed to start recording: {err:#}");
+                *recording_error = Some(format!("{err:#}"));
+            },
+        }
+    }
+
     fn handle_events(&self, event_pump: &mut EventPump) -> EventOutcome {
         let ctx = self.platform.borrow_mut().context();
         // let egui_wants_pointer_input = ctx.wants_pointer_input();
@@ -367,17 +816,12 @@ impl App<'_> {
                 Event::Window { window_id, win_event, .. } if window_id == sdl_wgpu.window.id() =>
                     match win_event {
                         WindowEvent::Close => return EventOutcome::Quit,
-                        WindowEvent::SizeChanged(w, h) =>
-                            if w > 0 && h > 0 {
-                                #[allow(clippy::as_conversions, clippy::cast_sign_loss)]
-                                {
-                                    sdl_wgpu.surface_configuration.width = w as u32;
-                                    sdl_wgpu.surface_configuration.height = h as u32;
-                                }
-                                sdl_wgpu
-                                    .surface
-                                    .configure(&sdl_wgpu.device, &sdl_wgpu.surface_configuration);
-                            },
+                        // The events carry sizes in points, the surface is sized in pixels.
+                        WindowEvent::SizeChanged(..)
+                        | WindowEvent::Moved(..)
+                        | WindowEvent::DisplayChanged(..) => {
+                            sdl_wgpu.resize_surface();
+                        },
                         _ => {},
                     },
                 Event::KeyDown { keycode: Some(key), .. } if !egui_wants_keyboard_input =>
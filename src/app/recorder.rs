@@ -0,0 +1,376 @@
+use std::fs::{self, File};
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::mpsc::{self, Receiver, SyncSender};
+use std::thread::{self, JoinHandle};
+use std::time::Instant;
+
+use anyhow::{Context, Result, anyhow};
+use clap::ValueEnum;
+
+use super::engine::FrameCapture;
+use super::screenshot::{self, ImageFileFormat};
+
+/// Container of the recorded frames.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, ValueEnum)]
+pub(crate) enum RecordingFormat {
+    /// One image file per frame, numbered, in a directory.
+    Images,
+    /// YUV4MPEG2 video, 8-bit 4:4:4, playable and convertible by most video tools. A new
+    /// numbered file is started when the frame size changes.
+    Y4m,
+    /// Headerless RGBA8 frames, back to back. A new numbered file is started when the frame size
+    /// changes.
+    Raw,
+}
+
+impl RecordingFormat {
+    pub(super) const ALL: [Self; 3] = [Self::Images, Self::Y4m, Self::Raw];
+
+    pub(super) const fn label(self) -> &'static str {
+        match self {
+            Self::Images => "Image sequence",
+            Self::Y4m => "Y4M video",
+            Self::Raw => "Raw RGBA video",
+        }
+    }
+}
+
+pub(super) struct RecordingConfiguration {
+    /// Directory receiving the recordings, created when missing.
+    pub dir:            PathBuf,
+    pub format:         RecordingFormat,
+    /// File format of the frames of an image sequence.
+    pub image_format:   ImageFileFormat,
+    /// Advance the simulation by exactly one frame at the target frame rate per recorded frame,
+    /// so that the recording plays back at the correct speed even when rendering is slower than
+    /// real time.
+    pub fixed_timestep: bool,
+}
+
+/// Records the presented frames to disk, writing them on a background thread.
+pub(super) struct Recorder {
+    frame_tx:           Option<SyncSender<FrameCapture>>,
+    handle:             Option<JoinHandle<Result<()>>>,
+    /// File or directory receiving the frames.
+    path:               PathBuf,
+    frames:             u64,
+    /// Number of files of a video recording, one more each time the frame size changes.
+    segments:           Arc<AtomicU32>,
+    started:            Instant,
+    /// Frame rate of the recording.
+    fps:                u32,
+    /// Advance the simulation by a fixed step per frame, copied from the configuration at start.
+    pub fixed_timestep: bool,
+}
+
+impl Recorder {
+    /// Frames queued for the writer before the main loop waits for it.
+    const QUEUE_LEN: usize = 8;
+
+    /// Starts a new recording at `fps` frames per second, named with a timestamp in milliseconds.
+    pub(super) fn start(cfg: &RecordingConfiguration, fps: u32) -> Result<Self> {
+        fs::create_dir_all(&cfg.dir)
+            .with_context(|| format!("Failed to create directory {}", cfg.dir.display()))?;
+
+        let name = format!("recording-{}", chrono::Local::now().format("%Y%m%d-%H%M%S-%3f"));
+        let path = match cfg.format {
+            RecordingFormat::Images => cfg.dir.join(name),
+            RecordingFormat::Y4m => cfg.dir.join(name).with_extension("y4m"),
+            RecordingFormat::Raw => cfg.dir.join(name).with_extension("rgba"),
+        };
+
+        let segments = Arc::new(AtomicU32::new(0));
+        let mut sink = match cfg.format {
+            RecordingFormat::Images => {
+                fs::create_dir_all(&path)
+                    .with_context(|| format!("Failed to create directory {}", path.display()))?;
+                Sink::Images { dir: path.clone(), format: cfg.image_format }
+            },
+            RecordingFormat::Y4m | RecordingFormat::Raw => Sink::Video(VideoSink {
+                path: path.clone(),
+                format: cfg.format,
+                fps,
+                segment: None,
+                segments: segments.clone(),
+            }),
+        };
+
+        let (frame_tx, frame_rx) = mpsc::sync_channel::<FrameCapture>(Self::QUEUE_LEN);
+
+        let handle = thread::Builder::new()
+            .name("recorder".into())
+            .spawn(move || sink.run(&frame_rx))
+            .context("Failed to spawn the recorder thread")?;
+
+        log::info!("Recording to {} at {fps} FPS", path.display());
+
+        Ok(Self {
+            frame_tx: Some(frame_tx),
+            handle: Some(handle),
+            path,
+            frames: 0,
+            segments,
+            started: Instant::now(),
+            fps,
+            fixed_timestep: cfg.fixed_timestep,
+        })
+    }
+
+    /// Queues a frame for writing. Waits for the writer when it is behind by more than a few
+    /// frames, so that no frame is lost.
+    pub(super) fn record(&mut self, capture: FrameCapture) -> Result<()> {
+        let frame_tx = self.frame_tx.as_ref().context("Recorder stopped")?;
+        if frame_tx.send(capture).is_err() {
+            // The writer has stopped on an error, which is reported when joining it.
+            return self.finish();
+        }
+        self.frames = self.frames.saturating_add(1);
+        Ok(())
+    }
+
+    pub(super) fn frames(&self) -> u64 {
+        self.frames
+    }
+
+    /// Returns the number of files written by a video recording.
+    pub(super) fn segments(&self) -> u32 {
+        self.segments.load(Ordering::Relaxed)
+    }
+
+    /// Returns the duration of the recording in seconds, at its frame rate.
+    #[allow(clippy::as_conversions, clippy::cast_precision_loss)]
+    pub(super) fn duration_s(&self) -> f64 {
+        self.frames as f64 / f64::from(self.fps.max(1))
+    }
+
+    /// Stops the recording, waiting for the queued frames to be written.
+    pub(super) fn finish(&mut self) -> Result<()> {
+        self.frame_tx = None;
+        let Some(handle) = self.handle.take() else {
+            return Ok(());
+        };
+
+        handle.join().map_err(|_err| anyhow!("Recorder thread panicked"))??;
+
+        log::info!(
+            "Recorded {} frames to {} in {:.1} s",
+            self.frames,
+            self.path.display(),
+            self.started.elapsed().as_secs_f32()
+        );
+        let segments = self.segments();
+        if segments > 1 {
+            log::info!("The frame size changed during the recording, split in {segments} files");
+        }
+
+        Ok(())
+    }
+}
+
+impl Drop for Recorder {
+    fn drop(&mut self) {
+        if let Err(err) = self.finish() {
+            log::error!("Recording failed: {err:#}");
+        }
+    }
+}
+
+enum Sink {
+    Images { dir: PathBuf, format: ImageFileFormat },
+    Video(VideoSink),
+}
+
+impl Sink {
+    fn run(&mut self, frame_rx: &Receiver<FrameCapture>) -> Result<()> {
+        for (index, capture) in frame_rx.iter().enumerate() {
+            match self {
+                Self::Images { dir, format } => {
+                    let path =
+                        dir.join(format!("frame-{index:06}")).with_extension(format.extension());
+                    screenshot::write_color(&path, *format, &capture)?;
+                },
+                Self::Video(video) => video.write(&capture)?,
+            }
+        }
+
+        if let Self::Video(video) = self {
+            video.finish_segment()?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Y4M or raw video. The containers hold frames of a single size, so the recording continues in
+/// a new file, a segment, whenever the frame size changes.
+struct VideoSink {
+    /// Path of the first segment, the following ones are numbered from 2.
+    path:     PathBuf,
+    format:   RecordingFormat,
+    fps:      u32,
+    /// The segment being written, `None` before the first frame.
+    segment:  Option<Segment>,
+    /// Number of segments started, shared with the recorder.
+    segments: Arc<AtomicU32>,
+}
+
+struct Segment {
+    writer: BufWriter<File>,
+    path:   PathBuf,
+    width:  u32,
+    height: u32,
+}
+
+impl VideoSink {
+    fn write(&mut self, capture: &FrameCapture) -> Result<()> {
+        if self.segment.as_ref().is_none_or(|segment| {
+            (segment.width, segment.height) != (capture.width, capture.height)
+        }) {
+            self.start_segment(capture.width, capture.height)?;
+        }
+        let writer = &mut self.segment.as_mut().context("No video segment")?.writer;
+
+        if self.format == RecordingFormat::Y4m {
+            writer.write_all(b"FRAME\n")?;
+            write_yuv444(writer, &capture.color)?;
+        } else {
+            writer.write_all(&capture.color)?;
+        }
+        Ok(())
+    }
+
+    // Finishes the current segment and starts the next one, with frames of `width` x `height`.
+    fn start_segment(&mut self, width: u32, height: u32) -> Result<()> {
+        self.finish_segment()?;
+
+        let index = self.segments.fetch_add(1, Ordering::Relaxed);
+        let path = segment_path(&self.path, index);
+        if index > 0 {
+            log::info!(
+                "Frame size changed to {width}x{height}, recording continues in {}",
+                path.display()
+            );
+        }
+
+        let mut writer = create_file(&path)?;
+        if self.format == RecordingFormat::Y4m {
+            writeln!(writer, "YUV4MPEG2 W{width} H{height} F{}:1 Ip A1:1 C444", self.fps)?;
+        }
+        self.segment = Some(Segment { writer, path, width, height });
+        Ok(())
+    }
+
+    fn finish_segment(&mut self) -> Result<()> {
+        let Some(Segment { mut writer, path, width, height }) = self.segment.take() else {
+            return Ok(());
+        };
+        writer.flush()?;
+
+        if self.format == RecordingFormat::Raw {
+            log::info!(
+                "Raw recording {}: rgba frames of {width}x{height}, e.g. ffmpeg -f rawvideo \
+                 -pixel_format rgba -video_size {width}x{height} -i <file> <output>",
+                path.display()
+            );
+        }
+        Ok(())
+    }
+}
+
+// Returns the path of the segment `index` of a video recording to `path`: `path` itself for the
+// first one, then the name of `path` followed by the segment number.
+fn segment_path(path: &Path, index: u32) -> PathBuf {
+    if index == 0 {
+        return path.to_path_buf();
+    }
+    let mut name = path.file_stem().unwrap_or_default().to_os_string();
+    name.push(format!("-{}", index.saturating_add(1)));
+    path.with_file_name(name).with_extension(path.extension().unwrap_or_default())
+}
+
+fn create_file(path: &Path) -> Result<BufWriter<File>> {
+    let file =
+        File::create(path).with_context(|| format!("Failed to create {}", path.display()))?;
+    Ok(BufWriter::new(file))
+}
+
+/// Writes the planes of an RGBA8 frame converted to limited range BT.601 `YCbCr`.
+#[allow(
+    clippy::as_conversions,
+    clippy::cast_possible_truncation,
+    clippy::cast_sign_loss,
+    clippy::arithmetic_side_effects
+)]
+fn write_yuv444(writer: &mut impl Write, color: &[u8]) -> Result<()> {
+    let num_pixels = color.len() / 4;
+    let mut planes = vec![0_u8; num_pixels * 3];
+    let (y_plane, chroma) = planes.split_at_mut(num_pixels);
+    // Blue and red difference chroma planes.
+    let (blue_plane, red_plane) = chroma.split_at_mut(num_pixels);
+
+    for (i, pixel) in color.chunks_exact(4).enumerate() {
+        let (r, g, b) = (f32::from(pixel[0]), f32::from(pixel[1]), f32::from(pixel[2]));
+        y_plane[i] = (16.0 + (65.738 * r + 129.057 * g + 25.064 * b) / 256.0).round() as u8;
+        blue_plane[i] = (128.0 + (-37.945 * r - 74.494 * g + 112.439 * b) / 256.0).round() as u8;
+        red_plane[i] = (128.0 + (112.439 * r - 94.154 * g - 18.285 * b) / 256.0).round() as u8;
+    }
+
+    writer.write_all(&planes)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::path::Path;
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicU32, Ordering};
+
+    use anyhow::Result;
+
+    use super::{RecordingFormat, VideoSink, segment_path};
+    use crate::app::engine::FrameCapture;
+
+    #[allow(clippy::arithmetic_side_effects, clippy::as_conversions)]
+    fn capture(width: u32, height: u32) -> FrameCapture {
+        let color = vec![0; width as usize * height as usize * 4];
+        FrameCapture { width, height, color, depth: Vec::new() }
+    }
+
+    #[test]
+    fn segment_path_numbers_the_following_segments() {
+        let path = Path::new("recordings/recording-1.y4m");
+
+        assert_eq!(segment_path(path, 0), path);
+        assert_eq!(segment_path(path, 1), Path::new("recordings/recording-1-2.y4m"));
+    }
+
+    #[test]
+    fn video_starts_a_segment_when_the_frame_size_changes() -> Result<()> {
+        let dir = std::env::temp_dir().join(format!("recorder-test-{}", std::process::id()));
+        fs::create_dir_all(&dir)?;
+        let path = dir.join("recording.rgba");
+        let mut video = VideoSink {
+            path:     path.clone(),
+            format:   RecordingFormat::Raw,
+            fps:      60,
+            segment:  None,
+            segments: Arc::new(AtomicU32::new(0)),
+        };
+
+        video.write(&capture(2, 2))?;
+        video.write(&capture(2, 2))?;
+        video.write(&capture(3, 1))?;
+        video.finish_segment()?;
+
+        let first_len = fs::metadata(&path)?.len();
+        let second_len = fs::metadata(dir.join("recording-2.rgba"))?.len();
+        fs::remove_dir_all(&dir)?;
+        assert_eq!((first_len, second_len), (32, 12));
+        assert_eq!(video.segments.load(Ordering::Relaxed), 2);
+        Ok(())
+    }
+}
@@ -1,7 +1,8 @@
 use std::cell::RefCell;
 use std::rc::Rc;
 
-use anyhow::{Context, Result};
+use anyhow::{Context, Result, ensure};
+use clap::ValueEnum;
 use egui_wgpu_backend::wgpu::{self, PipelineCompilationOptions};
 use wgpu::util::{BufferInitDescriptor, DeviceExt};
 use wgpu::{
@@ -9,6 +10,7 @@ use wgpu::{
     BindGroup,
     BindGroupDescriptor,
     BindGroupEntry,
+    BindGroupLayout,
     BindGroupLayoutDescriptor,
     BindGroupLayoutEntry,
     BindingResource,
@@ -17,8 +19,11 @@ use wgpu::{
     Buffer,
     BufferAddress,
     BufferUsages,
+    Color,
     ColorTargetState,
     ColorWrites,
+    CommandEncoder,
+    Device,
     Extent3d,
     FilterMode,
     FragmentState,
@@ -32,12 +37,14 @@ use wgpu::{
     RenderPassDescriptor,
     RenderPipeline,
     RenderPipelineDescriptor,
+    Sampler,
     SamplerBindingType,
     SamplerDescriptor,
     ShaderModuleDescriptor,
     ShaderSource,
     ShaderStages,
     StoreOp,
+    TexelCopyBufferInfo,
     TexelCopyBufferLayout,
     TexelCopyTextureInfo,
     Texture,
@@ -56,7 +63,89 @@ use wgpu::{
     VertexStepMode,
 };
 
+use crate::app::engine::DirtyRect;
 use crate::app::sdl_wgpu::{SdlWgpu, SdlWgpuConfiguration};
+use crate::app::staging_ring::StagingRing;
+
+/// How the internal render resolution is mapped onto the window surface.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, ValueEnum)]
+pub(crate) enum ScaleMode {
+    /// Stretch the image over the whole surface, ignoring the aspect ratio.
+    Stretch,
+    /// Scale uniformly to the largest size that fits, letterboxing the remaining area.
+    Fit,
+    /// Scale uniformly by the largest integer factor that fits, for pixel-exact output.
+    Integer,
+}
+
+impl ScaleMode {
+    pub(super) const ALL: [Self; 3] = [Self::Stretch, Self::Fit, Self::Integer];
+
+    pub(super) const fn label(self) -> &'static str {
+        match self {
+            Self::Stretch => "Stretch",
+            Self::Fit => "Aspect fit",
+            Self::Integer => "Integer scale",
+        }
+    }
+
+    /// Returns the `(x, y, width, height)` rectangle, in surface pixels, covered by an image of
+    /// size `src` presented on a surface of size `dst`.
+    #[allow(clippy::arithmetic_side_effects)]
+    pub(super) fn viewport(self, src: (u32, u32), dst: (u32, u32)) -> (u32, u32, u32, u32) {
+        let ((src_w, src_h), (dst_w, dst_h)) = (src, dst);
+
+        if src_w == 0 || src_h == 0 || dst_w == 0 || dst_h == 0 {
+            return (0, 0, dst_w, dst_h);
+        }
+
+        let (width, height) = match self {
+            Self::Stretch => (dst_w, dst_h),
+            Self::Integer if dst_w >= src_w && dst_h >= src_h => {
+                let factor = (dst_w / src_w).min(dst_h / src_h);
+                (src_w * factor, src_h * factor)
+            },
+            // The integer mode falls back to aspect fit when the surface is smaller than the image.
+            Self::Fit | Self::Integer => {
+                let (src_w, src_h, dst_w, dst_h) =
+                    (u64::from(src_w), u64::from(src_h), u64::from(dst_w), u64::from(dst_h));
+                let (width, height) = if dst_w * src_h <= dst_h * src_w {
+                    (dst_w, (dst_w * src_h / src_w).max(1))
+                } else {
+                    ((dst_h * src_w / src_h).max(1), dst_h)
+                };
+                // Both values are bounded by the (u32) surface size.
+                #[allow(clippy::as_conversions, clippy::cast_possible_truncation)]
+                (width as u32, height as u32)
+            },
+        };
+
+        ((dst_w - width) / 2, (dst_h - height) / 2, width, height)
+    }
+}
+
+/// How the software frame is transferred to the screen texture.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, ValueEnum)]
+pub(crate) enum UploadMode {
+    /// Copy the dirty regions with `Queue::write_texture`.
+    WriteTexture,
+    /// Copy the dirty regions into a mapped staging buffer, then to the texture on the GPU.
+    Staging,
+    /// Render the frame straight into a mapped staging buffer, skipping the frame buffer copy.
+    Mapped,
+}
+
+impl UploadMode {
+    pub(super) const ALL: [Self; 3] = [Self::WriteTexture, Self::Staging, Self::Mapped];
+
+    pub(super) const fn label(self) -> &'static str {
+        match self {
+            Self::WriteTexture => "Write texture",
+            Self::Staging => "Staging buffers",
+            Self::Mapped => "Render to mapped memory",
+        }
+    }
+}
 
 #[repr(C)]
 #[derive(Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
@@ -128,28 +217,24 @@ const QUAD_SHADER: &str = r"
 ";
 
 pub(super) struct ScreenQuad<'a> {
-    sdl_wgpu:      Rc<RefCell<SdlWgpu<'a>>>,
-    texture:       Texture,
-    pipeline:      RenderPipeline,
-    bind_group:    BindGroup,
-    vertex_buffer: Buffer,
-    num_vertices:  u32,
+    sdl_wgpu:          Rc<RefCell<SdlWgpu<'a>>>,
+    texture:           Texture,
+    staging_ring:      StagingRing,
+    sampler:           Sampler,
+    pipeline:          RenderPipeline,
+    bind_group_layout: BindGroupLayout,
+    bind_group:        BindGroup,
+    vertex_buffer:     Buffer,
+    num_vertices:      u32,
 }
 
 impl<'a> ScreenQuad<'a> {
-    pub(super) fn new(sdl_wgpu: Rc<RefCell<SdlWgpu<'a>>>) -> Self {
-        let SdlWgpuConfiguration { width, height, .. } = *sdl_wgpu.borrow().cfg.borrow();
+    /// Number of staging buffers, enough for the CPU to run two frames ahead of the GPU.
+    const STAGING_BUFFERS: usize = 3;
 
-        let screen_texture = sdl_wgpu.borrow_mut().device.create_texture(&TextureDescriptor {
-            label:           Some("Screen Render Texture"),
-            size:            Extent3d { width, height, depth_or_array_layers: 1 },
-            mip_level_count: 1,
-            sample_count:    1,
-            dimension:       TextureDimension::D2,
-            format:          TextureFormat::Rgba8Unorm,
-            usage:           TextureUsages::TEXTURE_BINDING | TextureUsages::COPY_DST,
-            view_formats:    &[TextureFormat::Rgba8Unorm],
-        });
+    pub(super) fn new(sdl_wgpu: Rc<RefCell<SdlWgpu<'a>>>) -> Result<Self> {
+        let SdlWgpuConfiguration { render_width: width, render_height: height, .. } =
+            *sdl_wgpu.borrow().cfg.borrow();
 
         let screen_sampler = sdl_wgpu.borrow_mut().device.create_sampler(&SamplerDescriptor {
             label: Some("Screen Texture Sampler"),
@@ -184,23 +269,17 @@ impl<'a> ScreenQuad<'a> {
                 ],
             });
 
-        let screen_texture_view = screen_texture.create_view(&TextureViewDescriptor::default());
+        Self::check_size(&sdl_wgpu.borrow().device, width, height)?;
+        let (screen_texture, screen_bind_group) = Self::create_texture(
+            &sdl_wgpu.borrow().device,
+            &screen_bind_group_layout,
+            &screen_sampler,
+            width,
+            height,
+        );
 
-        let screen_bind_group =
-            sdl_wgpu.borrow_mut().device.create_bind_group(&BindGroupDescriptor {
-                label:   Some("Screen Bind Group"),
-                layout:  &screen_bind_group_layout,
-                entries: &[
-                    BindGroupEntry {
-                        binding:  0,
-                        resource: BindingResource::TextureView(&screen_texture_view),
-                    },
-                    BindGroupEntry {
-                        binding:  1,
-                        resource: BindingResource::Sampler(&screen_sampler),
-                    },
-                ],
-            });
+        let staging_ring =
+            StagingRing::new(&sdl_wgpu.borrow().device, width, height, Self::STAGING_BUFFERS)?;
 
         let screen_pipeline_layout =
             sdl_wgpu.borrow_mut().device.create_pipeline_layout(&PipelineLayoutDescriptor {
@@ -255,67 +334,260 @@ impl<'a> ScreenQuad<'a> {
         #[allow(clippy::cast_possible_truncation, clippy::as_conversions)]
         let screen_num_vertices = VERTICES.len() as u32;
 
-        Self {
+        Ok(Self {
             sdl_wgpu,
             texture: screen_texture,
+            staging_ring,
+            sampler: screen_sampler,
             pipeline: screen_pipeline,
+            bind_group_layout: screen_bind_group_layout,
             bind_group: screen_bind_group,
             vertex_buffer: screen_vertex_buffer,
             num_vertices: screen_num_vertices,
+        })
+    }
+
+    // Fails when the device cannot create a screen texture of the given size, rather than
+    // letting wgpu panic.
+    fn check_size(device: &Device, width: u32, height: u32) -> Result<()> {
+        let max_dimension = device.limits().max_texture_dimension_2d;
+        ensure!(
+            (1..=max_dimension).contains(&width) && (1..=max_dimension).contains(&height),
+            "Render resolution {width}x{height} not supported, the dimensions must be between 1 \
+             and {max_dimension}"
+        );
+        Ok(())
+    }
+
+    // Creates the screen texture of the given size and the bind group that samples it.
+    fn create_texture(
+        device: &Device,
+        bind_group_layout: &BindGroupLayout,
+        sampler: &Sampler,
+        width: u32,
+        height: u32,
+    ) -> (Texture, BindGroup) {
+        let texture = device.create_texture(&TextureDescriptor {
+            label:           Some("Screen Render Texture"),
+            size:            Extent3d { width, height, depth_or_array_layers: 1 },
+            mip_level_count: 1,
+            sample_count:    1,
+            dimension:       TextureDimension::D2,
+            format:          TextureFormat::Rgba8Unorm,
+            usage:           TextureUsages::TEXTURE_BINDING | TextureUsages::COPY_DST,
+            view_formats:    &[TextureFormat::Rgba8Unorm],
+        });
+
+        let texture_view = texture.create_view(&TextureViewDescriptor::default());
+
+        let bind_group = device.create_bind_group(&BindGroupDescriptor {
+            label:   Some("Screen Bind Group"),
+            layout:  bind_group_layout,
+            entries: &[
+                BindGroupEntry {
+                    binding:  0,
+                    resource: BindingResource::TextureView(&texture_view),
+                },
+                BindGroupEntry { binding: 1, resource: BindingResource::Sampler(sampler) },
+            ],
+        });
+
+        (texture, bind_group)
+    }
+
+    /// Reallocates the screen texture, its bind group and the staging buffers for a new render
+    /// resolution.
+    pub(super) fn resize(&mut self, width: u32, height: u32) -> Result<()> {
+        if (width, height) == (self.width(), self.height()) {
+            return Ok(());
         }
+
+        let device = &self.sdl_wgpu.borrow().device;
+        Self::check_size(device, width, height)?;
+
+        let (texture, bind_group) =
+            Self::create_texture(device, &self.bind_group_layout, &self.sampler, width, height);
+
+        self.staging_ring = StagingRing::new(device, width, height, Self::STAGING_BUFFERS)?;
+        self.texture.destroy();
+        self.texture = texture;
+        self.bind_group = bind_group;
+
+        Ok(())
+    }
+
+    pub(super) fn upload_mode(&self) -> UploadMode {
+        self.sdl_wgpu.borrow().cfg.borrow().upload_mode
     }
 
     pub(super) fn width(&self) -> u32 {
-        self.sdl_wgpu.borrow().cfg.borrow().width
+        self.texture.width()
     }
 
     pub(super) fn height(&self) -> u32 {
-        self.sdl_wgpu.borrow().cfg.borrow().height
+        self.texture.height()
     }
 
-    fn update_texture(&self, pixel_data: &[u8]) -> Result<()> {
-        let width = self.texture.width();
-        let height = self.texture.height();
-
-        let bytes_per_row = Some(width.checked_mul(4).with_context(|| {
-            format!("Arithmetic overflow when computing bytes_per_row: 4 * {width}")
-        })?);
+    /// Uploads a region of the software frame buffer to the screen texture. `pixel_data` holds
+    /// the whole frame buffer, laid out like the texture. Returns the number of bytes uploaded.
+    #[allow(clippy::arithmetic_side_effects)]
+    pub(super) fn update_texture_region(
+        &self,
+        pixel_data: &[u8],
+        x: u32,
+        y: u32,
+        width: u32,
+        height: u32,
+    ) -> Result<u64> {
+        let texture_width = self.texture.width();
+
+        let bytes_per_row = texture_width.checked_mul(4).with_context(|| {
+            format!("Arithmetic overflow when computing bytes_per_row: 4 * {texture_width}")
+        })?;
+
+        let offset = u64::from(y)
+            .checked_mul(u64::from(bytes_per_row))
+            .and_then(|row_offset| row_offset.checked_add(u64::from(x) * 4))
+            .with_context(|| format!("Arithmetic overflow when computing offset of ({x}, {y})"))?;
 
         self.sdl_wgpu.borrow().queue.write_texture(
             TexelCopyTextureInfo {
                 texture:   &self.texture,
                 mip_level: 0,
-                origin:    Origin3d::ZERO,
+                origin:    Origin3d { x, y, z: 0 },
                 aspect:    TextureAspect::All,
             },
             pixel_data,
-            TexelCopyBufferLayout { offset: 0, bytes_per_row, rows_per_image: Some(height) },
+            TexelCopyBufferLayout {
+                offset,
+                bytes_per_row: Some(bytes_per_row),
+                rows_per_image: Some(height),
+            },
             Extent3d { width, height, depth_or_array_layers: 1 },
         );
 
-        Ok(())
+        Ok(u64::from(width) * u64::from(height) * 4)
     }
 
-    // Renders the full-screen quad that displays the software texture.
-    pub(super) fn render(&self, pixel_data: &[u8]) -> Result<()> {
-        self.update_texture(pixel_data)?;
+    /// Copies the given regions of the software frame buffer into a staging buffer and records
+    /// their copy to the screen texture. Returns the number of bytes uploaded, or `None` when no
+    /// staging buffer is available.
+    #[allow(clippy::arithmetic_side_effects, clippy::as_conversions)]
+    pub(super) fn upload_staged(
+        &mut self,
+        pixel_data: &[u8],
+        rects: &[DirtyRect],
+    ) -> Result<Option<u64>> {
+        let SdlWgpu { device, encoder, .. } = &mut *self.sdl_wgpu.borrow_mut();
+
+        let encoder = encoder.as_mut().context("Failed to get the encoder")?;
+
+        let src_bytes_per_row = self.texture.width() as usize * 4;
+        let bytes_per_row = self.staging_ring.bytes_per_row();
+
+        let Some(buffer) = self.staging_ring.stage(device, |staging, dst_bytes_per_row| {
+            for &DirtyRect { x, y, width, height } in rects {
+                let row_size = width as usize * 4;
+                for row in y as usize..(y + height) as usize {
+                    let src = row * src_bytes_per_row + x as usize * 4;
+                    let dst = row * dst_bytes_per_row + x as usize * 4;
+                    staging[dst..dst + row_size].copy_from_slice(&pixel_data[src..src + row_size]);
+                }
+            }
+        }) else {
+            return Ok(None);
+        };
+
+        let mut uploaded_bytes = 0;
+
+        for &rect in rects {
+            Self::copy_from_staging(encoder, buffer, bytes_per_row, &self.texture, rect);
+            uploaded_bytes += u64::from(rect.width) * u64::from(rect.height) * 4;
+        }
+
+        Ok(Some(uploaded_bytes))
+    }
+
+    /// Lets `draw` render the whole frame into a mapped staging buffer and records its copy to
+    /// the screen texture. `draw` receives the pixels and the row pitch in bytes. Returns the
+    /// number of bytes uploaded, or `None`, without calling `draw`, when no staging buffer is
+    /// available.
+    #[allow(clippy::arithmetic_side_effects)]
+    pub(super) fn upload_mapped(
+        &mut self,
+        draw: impl FnOnce(&mut [u8], usize),
+    ) -> Result<Option<u64>> {
+        let SdlWgpu { device, encoder, .. } = &mut *self.sdl_wgpu.borrow_mut();
+
+        let encoder = encoder.as_mut().context("Failed to get the encoder")?;
+
+        let bytes_per_row = self.staging_ring.bytes_per_row();
+
+        let Some(buffer) = self.staging_ring.stage(device, draw) else {
+            return Ok(None);
+        };
+
+        let (width, height) = (self.texture.width(), self.texture.height());
+
+        Self::copy_from_staging(
+            encoder,
+            buffer,
+            bytes_per_row,
+            &self.texture,
+            DirtyRect { x: 0, y: 0, width, height },
+        );
+
+        Ok(Some(u64::from(width) * u64::from(height) * 4))
+    }
+
+    #[allow(clippy::arithmetic_side_effects)]
+    fn copy_from_staging(
+        encoder: &mut CommandEncoder,
+        buffer: &Buffer,
+        bytes_per_row: u32,
+        texture: &Texture,
+        DirtyRect { x, y, width, height }: DirtyRect,
+    ) {
+        encoder.copy_buffer_to_texture(
+            TexelCopyBufferInfo {
+                buffer,
+                layout: TexelCopyBufferLayout {
+                    offset:         u64::from(y) * u64::from(bytes_per_row) + u64::from(x) * 4,
+                    bytes_per_row:  Some(bytes_per_row),
+                    rows_per_image: Some(height),
+                },
+            },
+            TexelCopyTextureInfo {
+                texture,
+                mip_level: 0,
+                origin: Origin3d { x, y, z: 0 },
+                aspect: TextureAspect::All,
+            },
+            Extent3d { width, height, depth_or_array_layers: 1 },
+        );
+    }
 
-        let SdlWgpu { frame, encoder, .. } = &mut *self.sdl_wgpu.borrow_mut();
+    // Renders the full-screen quad that displays the software texture.
+    pub(super) fn render(&self) -> Result<()> {
+        let SdlWgpu { cfg, frame_view, encoder, surface_configuration, .. } =
+            &mut *self.sdl_wgpu.borrow_mut();
 
-        #[allow(clippy::shadow_reuse)]
-        let frame = frame.as_ref().context("Failed to get frame")?;
+        let frame_view = frame_view.as_ref().context("Failed to get frame view")?;
 
-        let frame_view = frame.texture.create_view(&TextureViewDescriptor::default());
+        let (x, y, width, height) = cfg.borrow().scale_mode.viewport(
+            (self.width(), self.height()),
+            (surface_configuration.width, surface_configuration.height),
+        );
 
         let mut pass = encoder.as_mut().context("Failed to get the encoder")?.begin_render_pass(
             &RenderPassDescriptor {
                 label:                    Some("texture quad render pass"),
                 color_attachments:        &[Some(RenderPassColorAttachment {
-                    view:           &frame_view,
+                    view:           frame_view,
                     resolve_target: None,
                     ops:            Operations {
-                        // load: LoadOp::Clear(Color::BLUE),
-                        load:  LoadOp::Load,
+                        // Clear the letterbox area not covered by the viewport.
+                        load:  LoadOp::Clear(Color::BLACK),
                         store: StoreOp::Store,
                     },
                 })],
@@ -325,6 +597,8 @@ impl<'a> ScreenQuad<'a> {
             },
         );
 
+        #[allow(clippy::as_conversions, clippy::cast_precision_loss)]
+        pass.set_viewport(x as f32, y as f32, width as f32, height as f32, 0.0, 1.0);
         pass.set_pipeline(&self.pipeline);
         pass.set_bind_group(0, &self.bind_group, &[]);
         pass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
@@ -333,3 +607,50 @@ impl<'a> ScreenQuad<'a> {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::ScaleMode;
+
+    #[test]
+    fn stretch_covers_the_whole_surface() {
+        assert_eq!(ScaleMode::Stretch.viewport((320, 240), (1000, 500)), (0, 0, 1000, 500));
+        assert_eq!(ScaleMode::Stretch.viewport((320, 240), (100, 300)), (0, 0, 100, 300));
+    }
+
+    #[test]
+    fn fit_keeps_the_aspect_ratio_and_centers_the_image() {
+        // Pillarboxed on a wider surface.
+        assert_eq!(ScaleMode::Fit.viewport((320, 240), (1000, 600)), (100, 0, 800, 600));
+        // Letterboxed on a taller surface.
+        assert_eq!(ScaleMode::Fit.viewport((320, 240), (800, 1000)), (0, 200, 800, 600));
+        // Downscaled on a smaller surface.
+        assert_eq!(ScaleMode::Fit.viewport((320, 240), (160, 200)), (0, 40, 160, 120));
+    }
+
+    #[test]
+    fn integer_scales_by_the_largest_fitting_factor() {
+        assert_eq!(ScaleMode::Integer.viewport((320, 240), (1000, 600)), (180, 60, 640, 480));
+        assert_eq!(ScaleMode::Integer.viewport((320, 240), (1000, 800)), (20, 40, 960, 720));
+        assert_eq!(ScaleMode::Integer.viewport((320, 240), (320, 240)), (0, 0, 320, 240));
+    }
+
+    #[test]
+    fn integer_falls_back_to_fit_on_a_smaller_surface() {
+        for dst in [(160, 200), (300, 1000), (1000, 200)] {
+            assert_eq!(
+                ScaleMode::Integer.viewport((320, 240), dst),
+                ScaleMode::Fit.viewport((320, 240), dst)
+            );
+        }
+        assert_eq!(ScaleMode::Integer.viewport((320, 240), (300, 1000)), (0, 387, 300, 225));
+    }
+
+    #[test]
+    fn empty_sizes_cover_the_whole_surface() {
+        for mode in ScaleMode::ALL {
+            assert_eq!(mode.viewport((0, 240), (800, 600)), (0, 0, 800, 600));
+            assert_eq!(mode.viewport((320, 240), (0, 600)), (0, 0, 0, 600));
+        }
+    }
+}
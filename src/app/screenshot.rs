@@ -0,0 +1,215 @@
+use std::fs::{self, File};
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::thread;
+
+use anyhow::{Context, Result};
+use clap::ValueEnum;
+use image::ImageFormat;
+
+use super::engine::FrameCapture;
+
+/// File format of the color screenshots and of the grayscale depth dumps.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, ValueEnum)]
+pub(crate) enum ImageFileFormat {
+    Png,
+    Ppm,
+    Tga,
+    Bmp,
+}
+
+impl ImageFileFormat {
+    pub(super) const ALL: [Self; 4] = [Self::Png, Self::Ppm, Self::Tga, Self::Bmp];
+
+    pub(super) const fn label(self) -> &'static str {
+        match self {
+            Self::Png => "PNG",
+            Self::Ppm => "PPM",
+            Self::Tga => "TGA",
+            Self::Bmp => "BMP",
+        }
+    }
+
+    pub(super) const fn extension(self) -> &'static str {
+        match self {
+            Self::Png => "png",
+            Self::Ppm => "ppm",
+            Self::Tga => "tga",
+            Self::Bmp => "bmp",
+        }
+    }
+
+    const fn image_format(self) -> ImageFormat {
+        match self {
+            Self::Png => ImageFormat::Png,
+            Self::Ppm => ImageFormat::Pnm,
+            Self::Tga => ImageFormat::Tga,
+            Self::Bmp => ImageFormat::Bmp,
+        }
+    }
+}
+
+/// How the depth buffer is dumped along with a screenshot.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, ValueEnum)]
+pub(crate) enum DepthFormat {
+    /// 8-bit grayscale, normalized from the nearest (black) to the farthest (white) depth, in the
+    /// screenshot file format.
+    Gray,
+    /// Raw 32-bit float depth values, as a PFM file.
+    Float,
+}
+
+impl DepthFormat {
+    pub(super) const ALL: [Self; 2] = [Self::Gray, Self::Float];
+
+    pub(super) const fn label(self) -> &'static str {
+        match self {
+            Self::Gray => "Grayscale",
+            Self::Float => "32-bit float",
+        }
+    }
+}
+
+pub(super) struct ScreenshotConfiguration {
+    /// Directory receiving the screenshots, created when missing.
+    pub dir:    PathBuf,
+    pub format: ImageFileFormat,
+    /// Also dump the depth buffer, when set.
+    pub depth:  Option<DepthFormat>,
+}
+
+/// Writes `capture` to files named from `prefix` and a timestamp in the configured directory, on
+/// a background thread so that encoding does not stall the main loop. The depth buffer is only
+/// dumped when the capture has one.
+pub(super) fn save(
+    cfg: &ScreenshotConfiguration,
+    prefix: &str,
+    capture: FrameCapture,
+) -> Result<()> {
+    let dir = cfg.dir.clone();
+    let format = cfg.format;
+    let depth = cfg.depth.filter(|_| !capture.depth.is_empty());
+    let name = format!("{prefix}-{}", chrono::Local::now().format("%Y%m%d-%H%M%S-%3f"));
+
+    let _handle = thread::Builder::new()
+        .name("screenshot".into())
+        .spawn(move || match write_files(&dir, &name, format, depth, &capture) {
+            Ok(path) => log::info!("Screenshot saved to {}", path.display()),
+            Err(err) => log::error!("Failed to save screenshot: {err:#}"),
+        })
+        .context("Failed to spawn the screenshot thread")?;
+
+    Ok(())
+}
+
+fn write_files(
+    dir: &Path,
+    name: &str,
+    format: ImageFileFormat,
+    depth: Option<DepthFormat>,
+    capture: &FrameCapture,
+) -> Result<PathBuf> {
+    fs::create_dir_all(dir)
+        .with_context(|| format!("Failed to create directory {}", dir.display()))?;
+
+    let path = dir.join(name).with_extension(format.extension());
+    write_color(&path, format, capture)?;
+
+    match depth {
+        Some(DepthFormat::Gray) => {
+            let depth_path = dir.join(format!("{name}-depth")).with_extension(format.extension());
+            write_depth_gray(&depth_path, format, capture)?;
+        },
+        Some(DepthFormat::Float) => {
+            write_depth_pfm(&dir.join(format!("{name}-depth.pfm")), capture)?;
+        },
+        None => {},
+    }
+
+    Ok(path)
+}
+
+/// Writes the color buffer of `capture`. The alpha channel is dropped, as the formats without an
+/// alpha channel (PPM) and the viewers would otherwise disagree on the result.
+pub(super) fn write_color(
+    path: &Path,
+    format: ImageFileFormat,
+    capture: &FrameCapture,
+) -> Result<()> {
+    let rgb: Vec<u8> =
+        capture.color.chunks_exact(4).flat_map(|pixel| [pixel[0], pixel[1], pixel[2]]).collect();
+
+    image::save_buffer_with_format(
+        path,
+        &rgb,
+        capture.width,
+        capture.height,
+        image::ExtendedColorType::Rgb8,
+        format.image_format(),
+    )
+    .with_context(|| format!("Failed to write {}", path.display()))
+}
+
+fn write_depth_gray(path: &Path, format: ImageFileFormat, capture: &FrameCapture) -> Result<()> {
+    image::save_buffer_with_format(
+        path,
+        &depth_gray(&capture.depth),
+        capture.width,
+        capture.height,
+        image::ExtendedColorType::L8,
+        format.image_format(),
+    )
+    .with_context(|| format!("Failed to write {}", path.display()))
+}
+
+// Maps the depth values to gray levels, from black for the nearest to white for the farthest
+// covered pixel. The pixels not covered by any triangle are white and do not count in the range,
+// which would otherwise leave the covered ones black.
+#[allow(
+    clippy::arithmetic_side_effects,
+    clippy::as_conversions,
+    clippy::cast_possible_truncation,
+    clippy::cast_sign_loss
+)]
+fn depth_gray(depth: &[f32]) -> Vec<u8> {
+    let is_covered = |value: f32| value.is_finite() && value < FrameCapture::FAR_DEPTH;
+    let (min, max) = depth
+        .iter()
+        .filter(|&&value| is_covered(value))
+        .fold((f32::MAX, f32::MIN), |(min, max), &value| (min.min(value), max.max(value)));
+    let range = if max > min { max - min } else { 1.0 };
+
+    let level = |value: f32| ((value - min) / range * 255.0).round() as u8;
+    depth.iter().map(|&value| if is_covered(value) { level(value) } else { 255 }).collect()
+}
+
+/// Writes the depth buffer as a grayscale PFM (portable float map): a text header followed by
+/// little-endian `f32` rows from bottom to top.
+#[allow(clippy::as_conversions)]
+fn write_depth_pfm(path: &Path, capture: &FrameCapture) -> Result<()> {
+    let file =
+        File::create(path).with_context(|| format!("Failed to create {}", path.display()))?;
+    let mut writer = BufWriter::new(file);
+
+    // A negative scale marks little-endian data.
+    write!(writer, "Pf\n{} {}\n-1.0\n", capture.width, capture.height)?;
+    for row in capture.depth.chunks_exact(capture.width.max(1) as usize).rev() {
+        for depth in row {
+            writer.write_all(&depth.to_le_bytes())?;
+        }
+    }
+    writer.flush().with_context(|| format!("Failed to write {}", path.display()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::depth_gray;
+    use crate::app::engine::FrameCapture;
+
+    #[test]
+    fn depth_gray_spans_the_covered_pixels_only() {
+        let far = FrameCapture::FAR_DEPTH;
+        assert_eq!(depth_gray(&[far, 2.0, 4.0, 3.0, f32::NAN]), [255, 0, 255, 128, 255]);
+        assert_eq!(depth_gray(&[far, far]), [255, 255]);
+    }
+}
@@ -0,0 +1,154 @@
+use anyhow::{Context, Result, anyhow};
+use clap::ValueEnum;
+use egui_sdl2_platform::sdl2;
+use sdl2::event::{Event, WindowEvent};
+use sdl2::keyboard::Keycode;
+use sdl2::pixels::{Color, PixelFormatEnum};
+use sdl2::rect::Rect;
+
+use super::engine::Engine;
+use super::frame_timing::FrameTiming;
+use super::sdl_wgpu::SdlWgpuConfiguration;
+use super::window_mode::{self, WindowGeometry, WindowMode};
+use super::{App, AppConfiguration, scale_size};
+
+/// How the frames reach the window.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, ValueEnum)]
+pub(crate) enum Presenter {
+    /// Upload the frame buffer to a wgpu texture drawn with the GUI.
+    Wgpu,
+    /// Stream the frame buffer into an SDL texture, drawn by the SDL renderer, which falls back
+    /// to rendering in software. Runs without wgpu and without the GUI.
+    Sdl,
+}
+
+/// Runs the main loop presenting the frame buffer through the SDL renderer instead of wgpu, for
+/// machines where wgpu finds no adapter. The GUI is disabled; Escape quits.
+#[allow(
+    clippy::arithmetic_side_effects,
+    clippy::as_conversions,
+    clippy::cast_possible_truncation,
+    clippy::cast_precision_loss,
+    clippy::cast_possible_wrap,
+    clippy::cast_sign_loss
+)]
+pub(super) fn run(cfg: &AppConfiguration) -> Result<()> {
+    let SdlWgpuConfiguration {
+        title,
+        render_scale,
+        scale_mode,
+        track_window_size,
+        window_mode,
+        display_mode,
+        vsync,
+        ..
+    } = *cfg.sdl_wgpu_cfg.borrow();
+
+    let context = sdl2::init().map_err(|e| anyhow!("Failed to create sdl context: {}", e))?;
+    let video =
+        context.video().map_err(|e| anyhow!("Failed to initialize sdl video subsystem: {}", e))?;
+
+    let mut window = window_mode::window_builder(&video, &cfg.sdl_wgpu_cfg.borrow()).build()?;
+    let windowed_geometry = WindowGeometry::of(&window);
+    window_mode.apply(&mut window, display_mode)?;
+
+    let mut canvas_builder = window.into_canvas();
+    if vsync {
+        canvas_builder = canvas_builder.present_vsync();
+    }
+    let mut canvas = canvas_builder.build()?;
+    log::info!("Presenting with the SDL renderer {}", canvas.info().name);
+
+    cfg.fit_render_size(canvas.output_size().map_err(|e| anyhow!(e))?);
+    let (render_width, render_height) = {
+        let sdl_wgpu_cfg = cfg.sdl_wgpu_cfg.borrow();
+        (sdl_wgpu_cfg.render_width, sdl_wgpu_cfg.render_height)
+    };
+
+    let texture_creator = canvas.texture_creator();
+    let mut texture = texture_creator.create_texture_streaming(
+        PixelFormatEnum::RGBA32,
+        render_width,
+        render_height,
+    )?;
+    let mut engine = Engine::new_headless(cfg.engine_cfg.clone(), render_width, render_height)?;
+
+    let mut event_pump =
+        context.event_pump().map_err(|e| anyhow!("Failed to get sdl event pump: {}", e))?;
+
+    let clock = App::create_clock(cfg);
+    let mut timing = FrameTiming::new(cfg, clock.clone());
+
+    let frequency = clock.frequency();
+    let mut before_ticks = clock.now();
+    let mut last_title_ticks = before_ticks;
+
+    'main: loop {
+        for event in event_pump.poll_iter() {
+            match event {
+                Event::Quit { .. } | Event::KeyDown { keycode: Some(Keycode::Escape), .. } =>
+                    break 'main,
+                Event::Window { win_event: WindowEvent::SizeChanged(..), .. }
+                    if track_window_size =>
+                {
+                    let (drawable_width, drawable_height) =
+                        canvas.output_size().map_err(|e| anyhow!(e))?;
+                    let (new_width, new_height) = (
+                        scale_size(drawable_width, render_scale),
+                        scale_size(drawable_height, render_scale),
+                    );
+                    engine.resize(new_width, new_height)?;
+                    texture = texture_creator.create_texture_streaming(
+                        PixelFormatEnum::RGBA32,
+                        new_width,
+                        new_height,
+                    )?;
+                },
+                _ => {},
+            }
+        }
+
+        let elapsed_s = timing.elapsed_s(before_ticks);
+        timing.timestep.advance(elapsed_s, |step_s| engine.update(step_s as f32))?;
+        engine.render(timing.timestep.alpha())?;
+
+        let (frame_width, frame_height, color) = engine.frame();
+        texture
+            .update(None, color, frame_width as usize * 4)
+            .context("Failed to update the frame texture")?;
+
+        let (x, y, viewport_width, viewport_height) = scale_mode
+            .viewport((frame_width, frame_height), canvas.output_size().map_err(|e| anyhow!(e))?);
+        canvas.set_draw_color(Color::BLACK);
+        canvas.clear();
+        canvas
+            .copy(&texture, None, Rect::new(x as i32, y as i32, viewport_width, viewport_height))
+            .map_err(|e| anyhow!("Failed to draw the frame texture: {}", e))?;
+        let work_ticks = clock.now().saturating_sub(before_ticks);
+        canvas.present();
+
+        let end_ticks = timing.end_frame(before_ticks, clock.now(), work_ticks);
+
+        if end_ticks.saturating_sub(last_title_ticks) >= frequency / 4 {
+            canvas.window_mut().set_title(&format!(
+                "{title} - FPS: {:.2} - Mean frame time: {:.2} ms - {frame_width}x{frame_height}",
+                timing.frame_history.fps(),
+                timing.frame_history.mean_frame_time() * 1e3
+            ))?;
+            last_title_ticks = end_ticks;
+        }
+
+        before_ticks = end_ticks;
+    }
+
+    // The window mode is fixed, so the geometry only changes while windowed.
+    let geometry = match window_mode {
+        WindowMode::Windowed => WindowGeometry::of(canvas.window()),
+        WindowMode::Fullscreen | WindowMode::Desktop => windowed_geometry,
+    };
+    if let Err(err) = geometry.save(&cfg.window_state) {
+        log::error!("{err:#}");
+    }
+
+    Ok(())
+}
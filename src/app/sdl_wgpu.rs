@@ -4,40 +4,144 @@ use std::cell::RefCell;
 use std::rc::Rc;
 
 use anyhow::{Context, Result, anyhow};
+use clap::ValueEnum;
 use egui_sdl2_platform::sdl2;
 use egui_wgpu_backend::wgpu::{self, Features, Limits};
-use sdl2::video::Window;
+use sdl2::video::{DisplayMode, Window};
 use sdl2::{Sdl, VideoSubsystem};
 use wgpu::{
+    Adapter,
+    Backends,
     CommandEncoder,
     CommandEncoderDescriptor,
     Device,
     DeviceDescriptor,
+    Instance,
+    InstanceDescriptor,
     PowerPreference,
     PresentMode,
     Queue,
     RequestAdapterOptions,
     Surface,
     SurfaceConfiguration,
+    SurfaceError,
     SurfaceTexture,
     TextureFormat,
+    TextureUsages,
+    TextureView,
     TextureViewDescriptor,
 };
 
+use crate::app::engine::FrameCapture;
+use crate::app::screen_quad::{ScaleMode, UploadMode};
+use crate::app::window_capture::WindowCapture;
+use crate::app::window_mode::{self, WindowGeometry, WindowMode};
+
+/// Graphics APIs wgpu may use.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, ValueEnum)]
+pub(crate) enum GraphicsBackend {
+    /// Any backend available on the platform.
+    Auto,
+    Vulkan,
+    Metal,
+    Dx12,
+    /// OpenGL, or OpenGL ES.
+    Gl,
+}
+
+impl GraphicsBackend {
+    const fn backends(self) -> Backends {
+        match self {
+            Self::Auto => Backends::all(),
+            Self::Vulkan => Backends::VULKAN,
+            Self::Metal => Backends::METAL,
+            Self::Dx12 => Backends::DX12,
+            Self::Gl => Backends::GL,
+        }
+    }
+}
+
+/// Which adapter is preferred when several are available.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, ValueEnum)]
+pub(crate) enum AdapterPreference {
+    /// No preference, left to wgpu.
+    Default,
+    /// Favor battery life, usually an integrated GPU.
+    LowPower,
+    /// Favor performance, usually a discrete GPU.
+    HighPerformance,
+}
+
+impl AdapterPreference {
+    const fn power_preference(self) -> PowerPreference {
+        match self {
+            Self::Default => PowerPreference::None,
+            Self::LowPower => PowerPreference::LowPower,
+            Self::HighPerformance => PowerPreference::HighPerformance,
+        }
+    }
+}
+
+#[allow(clippy::struct_excessive_bools)]
 pub(super) struct SdlWgpuConfiguration {
-    pub title:      &'static str,
-    pub width:      u32,
-    pub height:     u32,
-    pub fullscreen: bool,
-    pub vsync:      bool,
+    pub title:                  &'static str,
+    pub width:                  u32,
+    pub height:                 u32,
+    /// Width of the internal software render target, independent of the window size.
+    pub render_width:           u32,
+    /// Height of the internal software render target, independent of the window size.
+    pub render_height:          u32,
+    /// Internal render resolution relative to the window size, used when tracking the window.
+    pub render_scale:           f32,
+    pub scale_mode:             ScaleMode,
+    /// Resize the internal render target whenever the window size changes.
+    pub track_window_size:      bool,
+    pub upload_mode:            UploadMode,
+    pub window_mode:            WindowMode,
+    /// Index of the display showing the window.
+    pub display:                i32,
+    /// Display mode of the exclusive fullscreen mode, the desktop one when `None`.
+    pub display_mode:           Option<DisplayMode>,
+    /// Initial position of the window, centered on the display when `None`.
+    pub position:               Option<(i32, i32)>,
+    pub vsync:                  bool,
+    /// Requested present mode, replaced by a fallback when the surface does not support it.
+    pub present_mode:           PresentMode,
+    pub backend:                GraphicsBackend,
+    pub adapter_preference:     AdapterPreference,
+    /// Only accept a fallback adapter, usually a software rasterizer.
+    pub force_fallback_adapter: bool,
+}
+
+/// Failures to acquire a surface texture, each skipping a frame.
+#[derive(Copy, Clone, Default)]
+pub(super) struct SurfaceErrorStats {
+    /// Surfaces lost or outdated, then reconfigured.
+    pub reconfigured: u64,
+    pub timeouts:     u64,
+    pub other:        u64,
+    /// Frames skipped since the last acquired surface texture.
+    consecutive:      u64,
 }
 
 pub(super) struct SdlWgpu<'a> {
     pub cfg:                   Rc<RefCell<SdlWgpuConfiguration>>,
     pub frame:                 Option<SurfaceTexture>,
+    /// View of the current frame texture, shared by the render passes of the frame.
+    pub frame_view:            Option<TextureView>,
     pub encoder:               Option<CommandEncoder>,
+    pub window_capture:        WindowCapture,
+    /// Window mode, display and display mode applied to the window.
+    window_mode:               WindowMode,
+    display:                   i32,
+    display_mode:              Option<DisplayMode>,
+    /// Last geometry of the window in windowed mode, restored when leaving fullscreen.
+    windowed_geometry:         WindowGeometry,
+    pub surface_errors:        SurfaceErrorStats,
     pub surface:               Surface<'a>,
     pub surface_configuration: SurfaceConfiguration,
+    /// Present modes supported by the surface, besides the automatic ones.
+    pub present_modes:         Vec<PresentMode>,
     pub surface_format:        TextureFormat,
     pub queue:                 Queue,
     pub device:                Device,
@@ -47,8 +151,27 @@ pub(super) struct SdlWgpu<'a> {
 }
 
 impl SdlWgpu<'_> {
+    /// Present modes offered in the settings.
+    pub(super) const PRESENT_MODES: [PresentMode; 6] = [
+        PresentMode::AutoVsync,
+        PresentMode::AutoNoVsync,
+        PresentMode::Fifo,
+        PresentMode::FifoRelaxed,
+        PresentMode::Mailbox,
+        PresentMode::Immediate,
+    ];
+
     pub(super) fn new(cfg: Rc<RefCell<SdlWgpuConfiguration>>) -> Result<Self> {
-        let SdlWgpuConfiguration { title, width, height, fullscreen, vsync } = *cfg.borrow();
+        let SdlWgpuConfiguration {
+            window_mode,
+            display,
+            display_mode,
+            present_mode,
+            backend,
+            adapter_preference,
+            force_fallback_adapter,
+            ..
+        } = *cfg.borrow();
 
         let context = sdl2::init().map_err(|e| anyhow!("Failed to create sdl context: {}", e))?;
 
@@ -56,33 +179,29 @@ impl SdlWgpu<'_> {
             .video()
             .map_err(|e| anyhow::anyhow!("Failed to initialize sdl video subsystem: {}", e))?;
 
-        let mut window_builder = video.window(title, width, height);
-
-        if fullscreen {
-            window_builder.fullscreen();
-            // window_builder.fullscreen_desktop();
-        } else {
-            window_builder.position_centered();
-        }
-
-        let window = window_builder.allow_highdpi().metal_view().build()?;
-
-        let instance = wgpu::Instance::new(&wgpu::InstanceDescriptor::default());
+        let mut window = window_mode::window_builder(&video, &cfg.borrow()).metal_view().build()?;
+        let windowed_geometry = WindowGeometry::of(&window);
+        // A saved position may put the window on another display than the configured one.
+        let display = window.display_index().unwrap_or(display);
+        cfg.borrow_mut().display = display;
+        window_mode.apply(&mut window, display_mode)?;
 
-        let surface = {
-            #[allow(unsafe_code)]
-            unsafe {
-                instance.create_surface_unsafe(wgpu::SurfaceTargetUnsafe::from_window(&window)?)?
-            }
-        };
+        let instance = Self::create_instance(backend);
+        let surface = Self::create_surface(&instance, &window)?;
 
         let adapter_opt = pollster::block_on(instance.request_adapter(&RequestAdapterOptions {
-            power_preference:       PowerPreference::None,
-            force_fallback_adapter: false,
-            compatible_surface:     Some(&surface),
+            power_preference: adapter_preference.power_preference(),
+            force_fallback_adapter,
+            compatible_surface: Some(&surface),
         }));
 
-        let adapter = adapter_opt.context("Failed to find wgpu adapter")?;
+        let adapter = adapter_opt.with_context(|| {
+            format!(
+                "Failed to find wgpu adapter (backend {backend:?}, fallback adapter \
+                 {force_fallback_adapter}), see --list_adapters"
+            )
+        })?;
+        log::info!("Using adapter {}", Self::describe_adapter(&adapter));
 
         let (device, queue) = match pollster::block_on(adapter.request_device(
             &DeviceDescriptor {
@@ -97,16 +216,21 @@ impl SdlWgpu<'_> {
             Err(e) => return Err(anyhow!("{}", e.to_string())),
         };
 
-        let surface_format = surface
-            .get_capabilities(&adapter)
-            .formats
-            .first()
-            .copied()
-            .context("No surface formats")?;
+        let surface_capabilities = surface.get_capabilities(&adapter);
+
+        let surface_format =
+            surface_capabilities.formats.first().copied().context("No surface formats")?;
+
+        // Window captures are shown by copying them to the surface texture, when allowed.
+        let surface_copy_dst = surface_capabilities.usages.contains(TextureUsages::COPY_DST);
 
-        let present_mode = if vsync { PresentMode::Fifo } else { PresentMode::Immediate };
+        let present_modes = surface_capabilities.present_modes.clone();
+        let present_mode = Self::fallback_present_mode(present_mode, &present_modes);
+        cfg.borrow_mut().present_mode = present_mode;
 
-        let surface_configuration = SurfaceConfiguration {
+        // On high density displays, the window size is in points and the surface in pixels.
+        let (width, height) = window.drawable_size();
+        let mut surface_configuration = SurfaceConfiguration {
             present_mode,
             // present_mode: wgpu::PresentMode::AutoVsync,
             format: surface_format,
@@ -117,6 +241,10 @@ impl SdlWgpu<'_> {
                 .context("Failed to get SurfaceConfiguration default config")?
         };
 
+        if surface_copy_dst {
+            surface_configuration.usage |= TextureUsages::COPY_DST;
+        }
+
         surface.configure(&device, &surface_configuration);
 
         Ok(Self {
@@ -127,20 +255,275 @@ impl SdlWgpu<'_> {
             surface,
             surface_format,
             surface_configuration,
+            present_modes,
             device,
             queue,
             frame: None,
+            frame_view: None,
             encoder: None,
+            window_capture: WindowCapture::new(surface_copy_dst),
+            window_mode,
+            display,
+            display_mode,
+            windowed_geometry,
+            surface_errors: SurfaceErrorStats::default(),
         })
     }
 
-    pub(super) fn init_render(&mut self) -> Result<()> {
-        let frame = self
-            .surface
-            .get_current_texture()
-            .map_err(|e| anyhow!("Failed to get current texture: {}", e))?;
+    fn create_instance(backend: GraphicsBackend) -> Instance {
+        Instance::new(&InstanceDescriptor { backends: backend.backends(), ..Default::default() })
+    }
+
+    // The surface must not outlive the window, which is not expressed by its lifetime.
+    fn create_surface(instance: &Instance, window: &Window) -> Result<Surface<'static>> {
+        #[allow(unsafe_code)]
+        let surface = unsafe {
+            instance.create_surface_unsafe(wgpu::SurfaceTargetUnsafe::from_window(window)?)?
+        };
+        Ok(surface)
+    }
+
+    #[allow(clippy::use_debug)]
+    fn describe_adapter(adapter: &Adapter) -> String {
+        let info = adapter.get_info();
+        format!(
+            "{} ({:?}, {:?}, vendor {:#06x}, device {:#06x}, driver {} {})",
+            info.name,
+            info.backend,
+            info.device_type,
+            info.vendor,
+            info.device,
+            info.driver,
+            info.driver_info
+        )
+    }
+
+    /// Prints the adapters of the configured backend with the formats and present modes they
+    /// support on a window surface.
+    #[allow(clippy::print_stdout, clippy::use_debug)]
+    pub(super) fn list_adapters(cfg: &SdlWgpuConfiguration) -> Result<()> {
+        let context = sdl2::init().map_err(|e| anyhow!("Failed to create sdl context: {}", e))?;
+        let video = context
+            .video()
+            .map_err(|e| anyhow!("Failed to initialize sdl video subsystem: {}", e))?;
+        // The surface capabilities depend on the window system, hence the hidden window.
+        let window =
+            video.window(cfg.title, cfg.width, cfg.height).hidden().metal_view().build()?;
+
+        let instance = Self::create_instance(cfg.backend);
+        let surface = Self::create_surface(&instance, &window)?;
+
+        let adapters = instance.enumerate_adapters(cfg.backend.backends());
+        if adapters.is_empty() {
+            println!("No adapters found for backend {:?}", cfg.backend);
+        }
+
+        for (index, adapter) in adapters.iter().enumerate() {
+            println!("[{index}] {}", Self::describe_adapter(adapter));
+
+            if !adapter.is_surface_supported(&surface) {
+                println!("    Cannot present to the window surface");
+                continue;
+            }
+            let capabilities = surface.get_capabilities(adapter);
+            println!("    Surface formats: {:?}", capabilities.formats);
+            println!("    Present modes:   {:?}", capabilities.present_modes);
+        }
+
+        Ok(())
+    }
+
+    pub(super) const fn present_mode_label(present_mode: PresentMode) -> &'static str {
+        match present_mode {
+            PresentMode::AutoVsync => "Auto vsync",
+            PresentMode::AutoNoVsync => "Auto no vsync",
+            PresentMode::Fifo => "Fifo (vsync)",
+            PresentMode::FifoRelaxed => "Fifo relaxed (adaptive vsync)",
+            PresentMode::Mailbox => "Mailbox",
+            PresentMode::Immediate => "Immediate (no vsync)",
+        }
+    }
+
+    /// Returns whether the surface supports `present_mode`. The automatic modes pick a supported
+    /// mode by themselves.
+    pub(super) fn supports_present_mode(&self, present_mode: PresentMode) -> bool {
+        Self::is_present_mode_supported(present_mode, &self.present_modes)
+    }
+
+    fn is_present_mode_supported(present_mode: PresentMode, supported: &[PresentMode]) -> bool {
+        matches!(present_mode, PresentMode::AutoVsync | PresentMode::AutoNoVsync)
+            || supported.contains(&present_mode)
+    }
+
+    // Returns `present_mode` when supported, otherwise the automatic mode with the same
+    // behavior regarding vsync.
+    fn fallback_present_mode(present_mode: PresentMode, supported: &[PresentMode]) -> PresentMode {
+        if Self::is_present_mode_supported(present_mode, supported) {
+            return present_mode;
+        }
+
+        let fallback = match present_mode {
+            PresentMode::Mailbox | PresentMode::Immediate => PresentMode::AutoNoVsync,
+            _ => PresentMode::AutoVsync,
+        };
+        log::warn!(
+            "Present mode {} not supported (supported: {}), falling back to {}",
+            Self::present_mode_label(present_mode),
+            supported
+                .iter()
+                .map(|&mode| Self::present_mode_label(mode))
+                .collect::<Vec<_>>()
+                .join(", "),
+            Self::present_mode_label(fallback)
+        );
+        fallback
+    }
+
+    // Reconfigures the surface when the present mode has been changed in the configuration.
+    fn update_present_mode(&mut self) {
+        let requested = self.cfg.borrow().present_mode;
+        if requested == self.surface_configuration.present_mode {
+            return;
+        }
+
+        let present_mode = Self::fallback_present_mode(requested, &self.present_modes);
+        self.cfg.borrow_mut().present_mode = present_mode;
+        if present_mode != self.surface_configuration.present_mode {
+            self.surface_configuration.present_mode = present_mode;
+            self.surface.configure(&self.device, &self.surface_configuration);
+            log::info!("Present mode set to {}", Self::present_mode_label(present_mode));
+        }
+    }
+
+    /// Applies the window mode, display and display mode when changed in the configuration. On
+    /// failure, the configuration is reset to the state of the window.
+    pub(super) fn update_window(&mut self) {
+        let (window_mode, display, display_mode) = {
+            let cfg = self.cfg.borrow();
+            (cfg.window_mode, cfg.display, cfg.display_mode)
+        };
+        if (window_mode, display, display_mode)
+            == (self.window_mode, self.display, self.display_mode)
+        {
+            return;
+        }
+
+        if let Err(err) = self.apply_window(window_mode, display, display_mode) {
+            log::error!("Failed to change the window mode: {err:#}");
+        }
+
+        self.window_mode = WindowMode::of(&self.window);
+        self.display = self.window.display_index().unwrap_or(self.display);
+        if self.window_mode == window_mode {
+            self.display_mode = display_mode;
+        }
+        let mut cfg = self.cfg.borrow_mut();
+        (cfg.window_mode, cfg.display, cfg.display_mode) =
+            (self.window_mode, self.display, self.display_mode);
+    }
+
+    fn apply_window(
+        &mut self,
+        window_mode: WindowMode,
+        display: i32,
+        display_mode: Option<DisplayMode>,
+    ) -> Result<()> {
+        if self.window_mode == WindowMode::Windowed {
+            self.windowed_geometry = WindowGeometry::of(&self.window);
+        }
+
+        // The window leaves fullscreen to move to another display, where it is centered.
+        if display != self.display {
+            WindowMode::Windowed.apply(&mut self.window, None)?;
+            let WindowGeometry { width, height, .. } = self.windowed_geometry;
+            let (x, y) = window_mode::centered_position(&self.video, display, width, height)?;
+            self.windowed_geometry = WindowGeometry { x, y, width, height };
+            self.window.set_position(x.into(), y.into());
+        }
+
+        window_mode.apply(&mut self.window, display_mode)?;
+
+        if window_mode == WindowMode::Windowed {
+            let WindowGeometry { x, y, width, height } = self.windowed_geometry;
+            self.window.set_size(width, height)?;
+            self.window.set_position(x.into(), y.into());
+        }
+
+        log::info!(
+            "Window mode set to {} on display {display} ({})",
+            window_mode.label(),
+            window_mode::display_mode_label(display_mode)
+        );
+        Ok(())
+    }
+
+    /// Number of surface pixels per window point, above 1 on high density displays.
+    #[allow(clippy::as_conversions, clippy::cast_precision_loss)]
+    pub(super) fn scale_factor(&self) -> f32 {
+        let (window_width, _) = self.window.size();
+        let (drawable_width, _) = self.window.drawable_size();
+        if window_width == 0 || drawable_width == 0 {
+            return 1.0;
+        }
+        drawable_width as f32 / window_width as f32
+    }
+
+    /// Reconfigures the surface when the drawable size of the window changed, which happens on
+    /// resizes and on moves between displays of different densities. Returns whether the
+    /// surface has been reconfigured.
+    pub(super) fn resize_surface(&mut self) -> bool {
+        let (width, height) = self.window.drawable_size();
+        let SurfaceConfiguration { width: surface_width, height: surface_height, .. } =
+            self.surface_configuration;
+        // Minimized windows have no drawable area.
+        if width == 0 || height == 0 || (width, height) == (surface_width, surface_height) {
+            return false;
+        }
+
+        (self.surface_configuration.width, self.surface_configuration.height) = (width, height);
+        self.surface.configure(&self.device, &self.surface_configuration);
+        log::debug!("Surface resized to {width}x{height} (scale {:.2})", self.scale_factor());
+        true
+    }
+
+    /// Returns the geometry of the window in windowed mode, the last one when in fullscreen.
+    pub(super) fn windowed_geometry(&self) -> WindowGeometry {
+        if WindowMode::of(&self.window) == WindowMode::Windowed {
+            WindowGeometry::of(&self.window)
+        } else {
+            self.windowed_geometry
+        }
+    }
+
+    /// Acquires the surface texture of the next frame and starts its command encoder. Returns
+    /// `false` when the frame must be skipped, the surface being reconfigured if needed, and an
+    /// error when out of memory.
+    pub(super) fn init_render(&mut self) -> Result<bool> {
+        self.update_present_mode();
+
+        let frame = match self.surface.get_current_texture() {
+            Ok(frame) => frame,
+            Err(err) => {
+                self.recover_surface(&err)?;
+                return Ok(false);
+            },
+        };
+        let errors = &mut self.surface_errors;
+        if errors.consecutive > 0 {
+            log::info!("Surface recovered after {} skipped frames", errors.consecutive);
+            errors.consecutive = 0;
+        }
 
-        // self.frame_view = Some(frame.texture.create_view(&TextureViewDescriptor::default()));
+        let capture_view = self.window_capture.begin_frame(
+            &self.device,
+            frame.texture.width(),
+            frame.texture.height(),
+            frame.texture.format(),
+        );
+        self.frame_view = Some(
+            capture_view
+                .unwrap_or_else(|| frame.texture.create_view(&TextureViewDescriptor::default())),
+        );
 
         self.frame = Some(frame);
 
@@ -148,13 +531,47 @@ impl SdlWgpu<'_> {
             label: Some("Main Command Encoder"),
         }));
 
+        Ok(true)
+    }
+
+    // Counts the failure to acquire a surface texture and recovers from it. Only the first
+    // failure of a streak is logged, as minimized windows may fail on every frame.
+    fn recover_surface(&mut self, err: &SurfaceError) -> Result<()> {
+        let errors = &mut self.surface_errors;
+        let first = errors.consecutive == 0;
+        errors.consecutive = errors.consecutive.saturating_add(1);
+
+        match err {
+            SurfaceError::Lost | SurfaceError::Outdated => {
+                errors.reconfigured = errors.reconfigured.saturating_add(1);
+                if first {
+                    log::warn!("{err}, reconfiguring the surface");
+                }
+                if !self.resize_surface() {
+                    self.surface.configure(&self.device, &self.surface_configuration);
+                }
+            },
+            SurfaceError::Timeout => {
+                errors.timeouts = errors.timeouts.saturating_add(1);
+                if first {
+                    log::warn!("{err}, skipping the frame");
+                }
+            },
+            SurfaceError::OutOfMemory =>
+                return Err(anyhow!("Failed to get current texture: {}", err)),
+            SurfaceError::Other => {
+                errors.other = errors.other.saturating_add(1);
+                if first {
+                    log::error!("{err}, skipping the frame");
+                }
+            },
+        }
+
         Ok(())
     }
 
     pub(super) fn clear(&mut self) -> Result<()> {
-        let frame = self.frame.as_ref().context("Failed to get frame")?;
-
-        let frame_view = frame.texture.create_view(&TextureViewDescriptor::default());
+        let frame_view = self.frame_view.as_ref().context("Failed to get frame view")?;
 
         let color = [0.0, 0.0, 0.0, 1.0];
 
@@ -162,7 +579,7 @@ impl SdlWgpu<'_> {
 
         encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
             color_attachments: &[Some(wgpu::RenderPassColorAttachment {
-                view:           &frame_view,
+                view:           frame_view,
                 resolve_target: None,
                 ops:            wgpu::Operations {
                     load:  wgpu::LoadOp::Clear(wgpu::Color {
@@ -183,15 +600,42 @@ impl SdlWgpu<'_> {
     }
 
     pub(super) fn present(&mut self) {
-        if let Some(encoder) = self.encoder.take() {
+        let captured = self.window_capture.is_capturing();
+
+        if let Some(mut encoder) = self.encoder.take() {
+            if let Some(frame) = self.frame.as_ref()
+                && let Err(err) =
+                    self.window_capture.end_frame(&self.device, &mut encoder, &frame.texture)
+            {
+                log::error!("Failed to capture the window: {err:#}");
+            }
             let command_buffer = encoder.finish();
             self.queue.submit(Some(command_buffer));
         }
+        self.window_capture.map_readbacks();
+        self.frame_view = None;
+
         if let Some(frame) = self.frame.take() {
-            frame.present();
+            if captured && !self.window_capture.copies_to_surface() {
+                // The surface texture has not been drawn, the previous frame stays on screen.
+                drop(frame);
+            } else {
+                frame.present();
+            }
         }
     }
 
+    /// Requests a capture of the next frame, GUI included.
+    pub(super) fn request_window_capture(&mut self) {
+        self.window_capture.request();
+    }
+
+    /// Returns the window captures whose readback has completed since the last call.
+    pub(super) fn take_window_captures(&mut self) -> Vec<FrameCapture> {
+        self.device.poll(wgpu::Maintain::Poll);
+        self.window_capture.take_completed()
+    }
+
     pub fn set_window_title(&mut self, title: &str) {
         #[allow(clippy::unwrap_used)]
         self.window.set_title(title).unwrap();
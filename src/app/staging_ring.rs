@@ -0,0 +1,255 @@
+#![allow(unused_results)]
+
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use anyhow::{Context, Result};
+use egui_wgpu_backend::wgpu;
+use wgpu::{
+    Buffer,
+    BufferAddress,
+    BufferAsyncError,
+    BufferDescriptor,
+    BufferUsages,
+    COPY_BYTES_PER_ROW_ALIGNMENT,
+    Device,
+    Maintain,
+    MapMode,
+};
+
+// Whether the CPU or the GPU owns a staging buffer.
+struct MapState {
+    // Set by the map callback once the GPU has released the buffer.
+    mapped:     Arc<AtomicBool>,
+    // Set by the map callback when mapping failed, the buffer must be mapped again.
+    map_failed: Arc<AtomicBool>,
+    // Used by a copy recorded in a previous frame, it must be mapped again before reuse.
+    in_flight:  bool,
+}
+
+// Completes the mapping of a staging buffer, from the callback of `map_async`.
+struct MapCompletion {
+    mapped:     Arc<AtomicBool>,
+    map_failed: Arc<AtomicBool>,
+}
+
+impl MapCompletion {
+    fn complete(self, result: &Result<(), BufferAsyncError>) {
+        if let Err(err) = result {
+            log::warn!("Failed to map a staging buffer, retrying on the next frame: {err}");
+        }
+        self.mapped.store(result.is_ok(), Ordering::Release);
+        self.map_failed.store(result.is_err(), Ordering::Release);
+    }
+}
+
+// The ownership of the buffers of a ring and the next one to write, kept apart from the buffers
+// themselves.
+struct RingState {
+    buffers: Vec<MapState>,
+    next:    usize,
+}
+
+impl RingState {
+    // The buffers are mapped at creation.
+    fn new(len: usize) -> Self {
+        let buffers = (0..len)
+            .map(|_| MapState {
+                mapped:     Arc::new(AtomicBool::new(true)),
+                map_failed: Arc::new(AtomicBool::new(false)),
+                in_flight:  false,
+            })
+            .collect();
+
+        Self { buffers, next: 0 }
+    }
+
+    // Calls `map` with the index of each buffer used by the copies of the previous frames, or
+    // whose mapping failed, and the completion to call once the buffer is mapped again.
+    fn recall(&mut self, mut map: impl FnMut(usize, MapCompletion)) {
+        for (index, state) in self.buffers.iter_mut().enumerate() {
+            if !state.in_flight && !state.map_failed.swap(false, Ordering::Acquire) {
+                continue;
+            }
+
+            map(
+                index,
+                MapCompletion {
+                    mapped:     state.mapped.clone(),
+                    map_failed: state.map_failed.clone(),
+                },
+            );
+            state.in_flight = false;
+        }
+    }
+
+    // Hands the next buffer over to the GPU and returns its index, or returns `None` when the GPU
+    // still owns it.
+    #[allow(clippy::arithmetic_side_effects)]
+    fn acquire(&mut self) -> Option<usize> {
+        let index = self.next;
+        let state = &mut self.buffers[index];
+
+        if !state.mapped.load(Ordering::Acquire) {
+            return None;
+        }
+
+        state.mapped.store(false, Ordering::Release);
+        state.in_flight = true;
+        self.next = (index + 1) % self.buffers.len();
+
+        Some(index)
+    }
+}
+
+/// A ring of staging buffers kept mapped for CPU writes, used to upload a frame to a texture
+/// with `copy_buffer_to_texture`.
+///
+/// Each buffer holds a whole frame with rows padded to `COPY_BYTES_PER_ROW_ALIGNMENT`. A buffer
+/// written in a frame is mapped again at the next call to [`StagingRing::stage`], when the copy
+/// that reads it has been submitted. A buffer whose mapping failed is mapped again at the
+/// following call, the frames in between falling back to another upload path.
+pub(super) struct StagingRing {
+    buffers:       Vec<Buffer>,
+    state:         RingState,
+    bytes_per_row: u32,
+}
+
+impl StagingRing {
+    pub(super) fn new(device: &Device, width: u32, height: u32, len: usize) -> Result<Self> {
+        let bytes_per_row = width
+            .checked_mul(4)
+            .and_then(|row_size| row_size.checked_next_multiple_of(COPY_BYTES_PER_ROW_ALIGNMENT))
+            .with_context(|| format!("Arithmetic overflow when padding row size of {width}"))?;
+
+        let size = BufferAddress::from(bytes_per_row)
+            .checked_mul(BufferAddress::from(height))
+            .context("Arithmetic overflow when computing staging buffer size")?;
+
+        let buffers = (0..len)
+            .map(|_| {
+                device.create_buffer(&BufferDescriptor {
+                    label: Some("Screen Staging Buffer"),
+                    size,
+                    usage: BufferUsages::MAP_WRITE | BufferUsages::COPY_SRC,
+                    mapped_at_creation: true,
+                })
+            })
+            .collect();
+
+        Ok(Self { buffers, state: RingState::new(len), bytes_per_row })
+    }
+
+    /// The padded size in bytes of a row in the staging buffers.
+    pub(super) fn bytes_per_row(&self) -> u32 {
+        self.bytes_per_row
+    }
+
+    /// Lets `write` fill the next mapped buffer of the ring, then unmaps it and returns it, ready
+    /// to be used as a copy source. `write` receives the mapped memory and its row pitch in
+    /// bytes. Returns `None`, without calling `write`, when the GPU still owns the buffer.
+    pub(super) fn stage(
+        &mut self,
+        device: &Device,
+        write: impl FnOnce(&mut [u8], usize),
+    ) -> Option<&Buffer> {
+        self.recall();
+        device.poll(Maintain::Poll);
+
+        let staging = &self.buffers[self.state.acquire()?];
+
+        {
+            let mut view = staging.slice(..).get_mapped_range_mut();
+            #[allow(clippy::as_conversions)]
+            write(&mut view, self.bytes_per_row as usize);
+        }
+
+        staging.unmap();
+
+        Some(staging)
+    }
+
+    // Maps again the buffers used by the copies of the previous frames, and those whose mapping
+    // failed.
+    fn recall(&mut self) {
+        let Self { buffers, state, .. } = self;
+        state.recall(|index, completion| {
+            buffers[index]
+                .slice(..)
+                .map_async(MapMode::Write, move |result| completion.complete(&result));
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use egui_wgpu_backend::wgpu::BufferAsyncError;
+
+    use super::{MapCompletion, RingState};
+
+    // Recalls the buffers of `state`, returning their indices and completions.
+    fn recall(state: &mut RingState) -> Vec<(usize, MapCompletion)> {
+        let mut recalled = Vec::new();
+        state.recall(|index, completion| recalled.push((index, completion)));
+        recalled
+    }
+
+    fn indices(recalled: &[(usize, MapCompletion)]) -> Vec<usize> {
+        recalled.iter().map(|(index, _)| *index).collect()
+    }
+
+    #[test]
+    fn buffers_are_acquired_in_turn_while_mapped() {
+        let mut state = RingState::new(3);
+        assert!(indices(&recall(&mut state)).is_empty());
+
+        assert_eq!(state.acquire(), Some(0));
+        assert_eq!(state.acquire(), Some(1));
+        assert_eq!(state.acquire(), Some(2));
+        // The ring wraps around to a buffer the GPU still owns.
+        assert_eq!(state.acquire(), None);
+        assert_eq!(state.next, 0);
+    }
+
+    #[test]
+    fn used_buffers_are_mapped_again_once() {
+        let mut state = RingState::new(2);
+        assert_eq!(state.acquire(), Some(0));
+        assert_eq!(state.acquire(), Some(1));
+
+        let mut recalled = recall(&mut state);
+        assert_eq!(indices(&recalled), [0, 1]);
+        // A pending mapping is not requested again.
+        assert!(recall(&mut state).is_empty());
+        assert_eq!(state.acquire(), None);
+
+        let (_, first) = recalled.remove(0);
+        first.complete(&Ok(()));
+        assert_eq!(state.acquire(), Some(0));
+        assert_eq!(state.acquire(), None);
+
+        let (_, second) = recalled.remove(0);
+        second.complete(&Ok(()));
+        assert_eq!(state.acquire(), Some(1));
+    }
+
+    #[test]
+    fn failed_mappings_are_retried() {
+        let mut state = RingState::new(1);
+        assert_eq!(state.acquire(), Some(0));
+
+        for (_, completion) in recall(&mut state) {
+            completion.complete(&Err(BufferAsyncError));
+        }
+        assert_eq!(state.acquire(), None);
+
+        let recalled = recall(&mut state);
+        assert_eq!(indices(&recalled), [0]);
+        assert!(recall(&mut state).is_empty());
+
+        for (_, completion) in recalled {
+            completion.complete(&Ok(()));
+        }
+        assert_eq!(state.acquire(), Some(0));
+    }
+}
@@ -1,8 +1,191 @@
+use std::fmt::Write as _;
 use std::io::{self, Write};
 
+use clap::ValueEnum;
+
+use super::engine::FrameCapture;
+
 #[allow(clippy::print_stdout)]
 pub(super) fn clear_terminal() -> io::Result<()> {
     // ANSI escape code to clear screen and move the cursor to the top-left corner.
     print!("\x1B[2J\x1B[1;1H");
     io::stdout().flush()
 }
+
+/// How the frames are drawn in the terminal.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, ValueEnum)]
+pub(crate) enum TerminalMode {
+    /// Two pixels per character with the upper half block, in 24-bit colors.
+    Truecolor,
+    /// One pixel per character from a ramp of ASCII characters, by luminance, for terminals
+    /// without color support.
+    Ascii,
+}
+
+/// Draws frames in the terminal, downsampled to its size and refreshed in place.
+pub(super) struct TerminalPresenter {
+    mode:    TerminalMode,
+    columns: u32,
+    rows:    u32,
+    /// Set once the screen has been cleared and the cursor hidden.
+    started: bool,
+    /// The escape sequences of the last frame, reused between frames.
+    output:  String,
+}
+
+impl TerminalPresenter {
+    /// Characters from the darkest to the brightest.
+    const ASCII_RAMP: &'static [u8] = b" .:-=+*#%@";
+
+    /// Creates a presenter drawing into at most `columns` x `rows` characters, or the size of
+    /// the terminal given by the `COLUMNS` and `LINES` environment variables, 80 x 24 otherwise.
+    pub(super) fn new(mode: TerminalMode, columns: Option<u32>, rows: Option<u32>) -> Self {
+        let env_size = |name: &str, default: u32| {
+            std::env::var(name).ok().and_then(|value| value.parse().ok()).unwrap_or(default)
+        };
+
+        Self {
+            mode,
+            columns: columns.unwrap_or_else(|| env_size("COLUMNS", 80)).max(1),
+            // The last row is kept free so that the final newline does not scroll the frame.
+            rows: rows.unwrap_or_else(|| env_size("LINES", 24).saturating_sub(1)).max(1),
+            started: false,
+            output: String::new(),
+        }
+    }
+
+    /// Draws `capture` over the previous frame.
+    pub(super) fn present(&mut self, capture: &FrameCapture) -> io::Result<()> {
+        if capture.width == 0 || capture.height == 0 {
+            return Ok(());
+        }
+
+        self.output.clear();
+        if !std::mem::replace(&mut self.started, true) {
+            // Clear the screen and hide the cursor.
+            self.output.push_str("\x1B[2J\x1B[?25l");
+        }
+        self.output.push_str("\x1B[H");
+
+        match self.mode {
+            TerminalMode::Truecolor => self.draw_half_blocks(capture),
+            TerminalMode::Ascii => self.draw_ascii(capture),
+        }
+
+        let mut stdout = io::stdout().lock();
+        stdout.write_all(self.output.as_bytes())?;
+        stdout.flush()
+    }
+
+    // Each character shows two vertically stacked pixels: the upper one as the foreground color
+    // of the upper half block, the lower one as the background color.
+    #[allow(clippy::arithmetic_side_effects)]
+    fn draw_half_blocks(&mut self, capture: &FrameCapture) {
+        let (columns, rows) = self.fit(capture);
+        let pixel_rows = rows * 2;
+
+        for row in 0..rows {
+            for column in 0..columns {
+                let [top_r, top_g, top_b] =
+                    Self::average(capture, column, row * 2, columns, pixel_rows);
+                let [bottom_r, bottom_g, bottom_b] =
+                    Self::average(capture, column, row * 2 + 1, columns, pixel_rows);
+                let _ = write!(
+                    self.output,
+                    "\x1B[38;2;{top_r};{top_g};{top_b}m\x1B[48;2;{bottom_r};{bottom_g};\
+                     {bottom_b}m\u{2580}"
+                );
+            }
+            self.output.push_str("\x1B[0m\n");
+        }
+    }
+
+    #[allow(
+        clippy::arithmetic_side_effects,
+        clippy::as_conversions,
+        clippy::cast_possible_truncation,
+        clippy::cast_precision_loss,
+        clippy::cast_sign_loss
+    )]
+    fn draw_ascii(&mut self, capture: &FrameCapture) {
+        let (columns, rows) = self.fit(capture);
+        let max_index = Self::ASCII_RAMP.len() - 1;
+
+        for row in 0..rows {
+            for column in 0..columns {
+                let [r, g, b] = Self::average(capture, column, row, columns, rows);
+                let luma = 0.299 * f32::from(r) + 0.587 * f32::from(g) + 0.114 * f32::from(b);
+                let index = ((luma / 255.0) * max_index as f32).round() as usize;
+                self.output.push(char::from(Self::ASCII_RAMP[index.min(max_index)]));
+            }
+            self.output.push('\n');
+        }
+    }
+
+    // Returns the number of characters covering the frame within the terminal size, keeping the
+    // aspect ratio of the frame with characters about twice as high as wide.
+    #[allow(
+        clippy::arithmetic_side_effects,
+        clippy::as_conversions,
+        clippy::cast_possible_truncation,
+        clippy::cast_sign_loss
+    )]
+    fn fit(&self, capture: &FrameCapture) -> (u32, u32) {
+        let aspect = f64::from(capture.height) / f64::from(capture.width);
+        // Height in characters of the frame drawn `columns` characters wide.
+        let rows_for = |columns: u32| (f64::from(columns) * aspect / 2.0).round() as u32;
+
+        let columns = self.columns.min(capture.width);
+        let rows = rows_for(columns);
+        if rows <= self.rows {
+            return (columns, rows.max(1));
+        }
+
+        let columns = (f64::from(columns) * f64::from(self.rows) / f64::from(rows)) as u32;
+        (columns.max(1), self.rows)
+    }
+
+    // Averages the color of the frame pixels covered by the cell (`x`, `y`) of a grid of
+    // `columns` x `rows` cells laid over the frame.
+    #[allow(
+        clippy::arithmetic_side_effects,
+        clippy::as_conversions,
+        clippy::cast_possible_truncation
+    )]
+    fn average(capture: &FrameCapture, x: u32, y: u32, columns: u32, rows: u32) -> [u8; 3] {
+        let span = |cell: u32, cells: u32, size: u32| {
+            let start = u64::from(cell) * u64::from(size) / u64::from(cells);
+            let end = u64::from(cell + 1) * u64::from(size) / u64::from(cells);
+            (start as usize, (end as usize).max(start as usize + 1).min(size as usize))
+        };
+        let (x0, x1) = span(x, columns, capture.width);
+        let (y0, y1) = span(y, rows, capture.height);
+
+        let width = capture.width as usize;
+        let mut sum = [0_u64; 3];
+        for row in y0..y1 {
+            let pixels = &capture.color[(row * width + x0) * 4..(row * width + x1) * 4];
+            for pixel in pixels.chunks_exact(4) {
+                for (total, &channel) in sum.iter_mut().zip(pixel) {
+                    *total += u64::from(channel);
+                }
+            }
+        }
+
+        let count = ((x1 - x0) * (y1 - y0)).max(1) as u64;
+        sum.map(|total| (total / count) as u8)
+    }
+}
+
+impl Drop for TerminalPresenter {
+    #[allow(clippy::print_stdout)]
+    fn drop(&mut self) {
+        if self.started {
+            // Restore the colors and the cursor.
+            print!("\x1B[0m\x1B[?25h");
+            if let Err(err) = io::stdout().flush() {
+                log::error!("Failed to restore the terminal: {err}");
+            }
+        }
+    }
+}
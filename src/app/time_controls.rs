@@ -0,0 +1,47 @@
+/// Debug controls of the simulation time: pause, single steps and time scale.
+pub(super) struct TimeControls {
+    pub paused:     bool,
+    /// Speed of the simulation relative to real time.
+    pub time_scale: f32,
+    /// Simulation steps requested while paused and not run yet.
+    pending_steps:  u32,
+}
+
+impl TimeControls {
+    pub(super) const MAX_TIME_SCALE: f32 = 4.0;
+    pub(super) const MIN_TIME_SCALE: f32 = 1.0 / 16.0;
+    /// Factor applied to the time scale by the slow down and speed up hotkeys.
+    const TIME_SCALE_STEP: f32 = 2.0;
+
+    pub(super) fn new() -> Self {
+        Self { paused: false, time_scale: 1.0, pending_steps: 0 }
+    }
+
+    pub(super) fn toggle_pause(&mut self) {
+        self.paused = !self.paused;
+        self.pending_steps = 0;
+    }
+
+    /// Pauses the simulation, and advances it by a single step.
+    pub(super) fn step(&mut self) {
+        self.paused = true;
+        self.pending_steps = self.pending_steps.saturating_add(1);
+    }
+
+    /// Returns the number of single steps to run in this frame.
+    pub(super) fn take_steps(&mut self) -> u32 {
+        std::mem::take(&mut self.pending_steps)
+    }
+
+    pub(super) fn slow_down(&mut self) {
+        self.set_time_scale(self.time_scale / Self::TIME_SCALE_STEP);
+    }
+
+    pub(super) fn speed_up(&mut self) {
+        self.set_time_scale(self.time_scale * Self::TIME_SCALE_STEP);
+    }
+
+    pub(super) fn set_time_scale(&mut self, time_scale: f32) {
+        self.time_scale = time_scale.clamp(Self::MIN_TIME_SCALE, Self::MAX_TIME_SCALE);
+    }
+}
@@ -0,0 +1,152 @@
+use anyhow::Result;
+use clap::ValueEnum;
+
+/// How the simulation time advances between frames.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, ValueEnum)]
+pub(crate) enum TimestepMode {
+    /// Update in constant steps of the target frame time, as many as the elapsed time requires,
+    /// and interpolate the rendered state between the last two steps.
+    Fixed,
+    /// Update once per frame by the elapsed time.
+    Variable,
+}
+
+impl TimestepMode {
+    pub(super) const ALL: [Self; 2] = [Self::Fixed, Self::Variable];
+
+    pub(super) const fn label(self) -> &'static str {
+        match self {
+            Self::Fixed => "Fixed",
+            Self::Variable => "Variable",
+        }
+    }
+}
+
+/// Splits the elapsed frame time into simulation steps according to the timestep mode.
+pub(super) struct Timestep {
+    pub mode:    TimestepMode,
+    /// Duration of a fixed step.
+    step:        f64,
+    /// Elapsed time not consumed by the fixed steps yet.
+    accumulator: f64,
+    /// Maximum number of steps per frame; the remaining time is dropped beyond it, so that a long
+    /// stall does not trigger an ever growing catch-up.
+    max_steps:   u32,
+}
+
+impl Timestep {
+    pub(super) fn new(mode: TimestepMode, step: f64, max_steps: u32) -> Self {
+        Self { mode, step, accumulator: 0.0, max_steps: max_steps.max(1) }
+    }
+
+    /// Feeds the time elapsed since the previous frame, in the same unit as the step, and calls
+    /// `update` with the duration of each simulation step to run. Returns the number of steps.
+    #[allow(clippy::arithmetic_side_effects)]
+    pub(super) fn advance(
+        &mut self,
+        elapsed: f64,
+        mut update: impl FnMut(f64) -> Result<()>,
+    ) -> Result<u32> {
+        let max_elapsed = self.step * f64::from(self.max_steps);
+
+        match self.mode {
+            TimestepMode::Fixed => {
+                self.accumulator = (self.accumulator + elapsed).min(max_elapsed);
+                let mut steps = 0;
+                while self.accumulator >= self.step && steps < self.max_steps {
+                    update(self.step)?;
+                    self.accumulator -= self.step;
+                    steps += 1;
+                }
+                Ok(steps)
+            },
+            TimestepMode::Variable => {
+                self.accumulator = 0.0;
+                update(elapsed.min(max_elapsed))?;
+                Ok(1)
+            },
+        }
+    }
+
+    /// Runs a single simulation step of the fixed step duration, regardless of the elapsed time.
+    pub(super) fn step(&self, mut update: impl FnMut(f64) -> Result<()>) -> Result<()> {
+        update(self.step)
+    }
+
+    /// Returns the position of the rendered frame between the last two simulation steps, from 0
+    /// (the previous step) to 1 (the last step).
+    #[allow(clippy::as_conversions, clippy::cast_possible_truncation)]
+    pub(super) fn alpha(&self) -> f32 {
+        match self.mode {
+            TimestepMode::Fixed if self.step > 0.0 => (self.accumulator / self.step) as f32,
+            TimestepMode::Fixed | TimestepMode::Variable => 1.0,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::Result;
+
+    use super::{Timestep, TimestepMode};
+    use crate::app::clock::{Clock, ManualClock};
+
+    // Advances the clock by `ticks` and feeds the elapsed time to `timestep`, returning the
+    // durations of the steps run.
+    #[allow(clippy::as_conversions, clippy::cast_precision_loss)]
+    fn advance(timestep: &mut Timestep, clock: &ManualClock, ticks: u64) -> Result<Vec<f64>> {
+        let start = clock.now();
+        clock.advance(ticks);
+        let elapsed = clock.to_seconds(clock.now().saturating_sub(start) as f64);
+
+        let mut steps = Vec::new();
+        let num_steps = timestep.advance(elapsed, |dt| {
+            steps.push(dt);
+            Ok(())
+        })?;
+        assert_eq!(usize::try_from(num_steps)?, steps.len());
+        Ok(steps)
+    }
+
+    #[test]
+    #[allow(clippy::float_cmp)]
+    fn fixed_accumulates_the_remainder() -> Result<()> {
+        // Steps of 0.25 s, with one tick per 1/8 s so that all the times are exact.
+        let clock = ManualClock::new(8);
+        let mut timestep = Timestep::new(TimestepMode::Fixed, 0.25, 4);
+
+        assert_eq!(advance(&mut timestep, &clock, 5)?, [0.25, 0.25]);
+        assert_eq!(timestep.alpha(), 0.5);
+        assert_eq!(advance(&mut timestep, &clock, 1)?, [0.25]);
+        assert_eq!(timestep.alpha(), 0.0);
+        assert_eq!(advance(&mut timestep, &clock, 1)?, []);
+        assert_eq!(timestep.alpha(), 0.5);
+        Ok(())
+    }
+
+    #[test]
+    #[allow(clippy::float_cmp)]
+    fn fixed_drops_the_time_beyond_the_max_steps() -> Result<()> {
+        let clock = ManualClock::new(8);
+        let mut timestep = Timestep::new(TimestepMode::Fixed, 0.25, 4);
+
+        assert_eq!(advance(&mut timestep, &clock, 80)?, [0.25; 4]);
+        // The remainder of the stall is not carried over.
+        assert_eq!(timestep.alpha(), 0.0);
+        assert_eq!(advance(&mut timestep, &clock, 1)?, []);
+        Ok(())
+    }
+
+    #[test]
+    #[allow(clippy::float_cmp)]
+    fn variable_runs_one_step_of_the_elapsed_time() -> Result<()> {
+        let clock = ManualClock::new(8);
+        let mut timestep = Timestep::new(TimestepMode::Variable, 0.25, 4);
+
+        assert_eq!(advance(&mut timestep, &clock, 3)?, [0.375]);
+        assert_eq!(timestep.alpha(), 1.0);
+        // Clamped like the fixed steps.
+        assert_eq!(advance(&mut timestep, &clock, 80)?, [1.0]);
+        Ok(())
+    }
+}
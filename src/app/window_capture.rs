@@ -0,0 +1,272 @@
+#![allow(unused_results)]
+
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU8, Ordering};
+
+use anyhow::{Context, Result, ensure};
+use egui_wgpu_backend::wgpu;
+use wgpu::{
+    Buffer,
+    BufferAddress,
+    BufferDescriptor,
+    BufferUsages,
+    COPY_BYTES_PER_ROW_ALIGNMENT,
+    CommandEncoder,
+    Device,
+    Extent3d,
+    MapMode,
+    Origin3d,
+    TexelCopyBufferInfo,
+    TexelCopyBufferLayout,
+    TexelCopyTextureInfo,
+    Texture,
+    TextureAspect,
+    TextureDescriptor,
+    TextureDimension,
+    TextureFormat,
+    TextureUsages,
+    TextureView,
+    TextureViewDescriptor,
+};
+
+use crate::app::engine::FrameCapture;
+
+// States of a readback buffer, set by the map callback.
+const MAP_PENDING: u8 = 0;
+const MAP_DONE: u8 = 1;
+const MAP_FAILED: u8 = 2;
+
+struct Readback {
+    buffer:        Buffer,
+    state:         Arc<AtomicU8>,
+    width:         u32,
+    height:        u32,
+    bytes_per_row: u32,
+    format:        TextureFormat,
+    // Set once `map_async` has been called, after the submission of the copy.
+    map_requested: bool,
+}
+
+/// Captures whole window frames, GUI included.
+///
+/// A captured frame is rendered into an offscreen texture instead of the surface texture, then
+/// copied both to the surface, when the surface allows it, and to a readback buffer. The buffer
+/// is mapped asynchronously and polled at the following frames, so that the main loop never
+/// waits for the GPU.
+pub(super) struct WindowCapture {
+    requested:       bool,
+    /// The offscreen target, kept between captures while the surface size and format match.
+    target:          Option<Texture>,
+    /// Set while the current frame is rendered into `target`.
+    capturing:       bool,
+    /// The surface texture can be a copy destination, to show the captured frame.
+    copy_to_surface: bool,
+    readbacks:       Vec<Readback>,
+}
+
+impl WindowCapture {
+    pub(super) fn new(copy_to_surface: bool) -> Self {
+        Self {
+            requested: false,
+            target: None,
+            capturing: false,
+            copy_to_surface,
+            readbacks: vec![],
+        }
+    }
+
+    /// Captures the next rendered frame.
+    pub(super) fn request(&mut self) {
+        self.requested = true;
+    }
+
+    /// Returns `true` when the current frame is rendered offscreen.
+    pub(super) fn is_capturing(&self) -> bool {
+        self.capturing
+    }
+
+    /// Whether the captured frame is also copied to the surface texture, which is otherwise
+    /// discarded.
+    pub(super) fn copies_to_surface(&self) -> bool {
+        self.copy_to_surface
+    }
+
+    /// Starts capturing the frame if requested, returning the view to render it into.
+    pub(super) fn begin_frame(
+        &mut self,
+        device: &Device,
+        width: u32,
+        height: u32,
+        format: TextureFormat,
+    ) -> Option<TextureView> {
+        if !std::mem::take(&mut self.requested) {
+            return None;
+        }
+
+        let target = match self.target.take() {
+            Some(target)
+                if (target.width(), target.height(), target.format())
+                    == (width, height, format) =>
+                target,
+            _ => device.create_texture(&TextureDescriptor {
+                label: Some("Window Capture Texture"),
+                size: Extent3d { width, height, depth_or_array_layers: 1 },
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: TextureDimension::D2,
+                format,
+                usage: TextureUsages::RENDER_ATTACHMENT | TextureUsages::COPY_SRC,
+                view_formats: &[],
+            }),
+        };
+
+        let view = target.create_view(&TextureViewDescriptor::default());
+        self.target = Some(target);
+        self.capturing = true;
+        Some(view)
+    }
+
+    /// Records the copies of the captured frame to a new readback buffer and, when supported, to
+    /// `surface_texture`. The buffer must be mapped with [`WindowCapture::map_readbacks`] once
+    /// the copies are submitted.
+    pub(super) fn end_frame(
+        &mut self,
+        device: &Device,
+        encoder: &mut CommandEncoder,
+        surface_texture: &Texture,
+    ) -> Result<()> {
+        if !std::mem::take(&mut self.capturing) {
+            return Ok(());
+        }
+
+        let target = self.target.as_ref().context("Window capture target missing")?;
+        let (width, height, format) = (target.width(), target.height(), target.format());
+
+        ensure!(
+            matches!(
+                format,
+                TextureFormat::Rgba8Unorm
+                    | TextureFormat::Rgba8UnormSrgb
+                    | TextureFormat::Bgra8Unorm
+                    | TextureFormat::Bgra8UnormSrgb
+            ),
+            "Unsupported window capture format {format:?}"
+        );
+
+        let bytes_per_row = width
+            .checked_mul(4)
+            .and_then(|row_size| row_size.checked_next_multiple_of(COPY_BYTES_PER_ROW_ALIGNMENT))
+            .with_context(|| format!("Arithmetic overflow when padding row size of {width}"))?;
+
+        let size = BufferAddress::from(bytes_per_row)
+            .checked_mul(BufferAddress::from(height))
+            .context("Arithmetic overflow when computing readback buffer size")?;
+
+        let buffer = device.create_buffer(&BufferDescriptor {
+            label: Some("Window Capture Readback Buffer"),
+            size,
+            usage: BufferUsages::MAP_READ | BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        let extent = Extent3d { width, height, depth_or_array_layers: 1 };
+        let source = TexelCopyTextureInfo {
+            texture:   target,
+            mip_level: 0,
+            origin:    Origin3d::ZERO,
+            aspect:    TextureAspect::All,
+        };
+
+        encoder.copy_texture_to_buffer(
+            source,
+            TexelCopyBufferInfo {
+                buffer: &buffer,
+                layout: TexelCopyBufferLayout {
+                    offset:         0,
+                    bytes_per_row:  Some(bytes_per_row),
+                    rows_per_image: Some(height),
+                },
+            },
+            extent,
+        );
+
+        if self.copy_to_surface {
+            encoder.copy_texture_to_texture(
+                source,
+                TexelCopyTextureInfo {
+                    texture:   surface_texture,
+                    mip_level: 0,
+                    origin:    Origin3d::ZERO,
+                    aspect:    TextureAspect::All,
+                },
+                extent,
+            );
+        }
+
+        self.readbacks.push(Readback {
+            buffer,
+            state: Arc::new(AtomicU8::new(MAP_PENDING)),
+            width,
+            height,
+            bytes_per_row,
+            format,
+            map_requested: false,
+        });
+
+        Ok(())
+    }
+
+    /// Requests the mapping of the readback buffers recorded in this frame, after the submission
+    /// of their copies.
+    pub(super) fn map_readbacks(&mut self) {
+        for readback in self.readbacks.iter_mut().filter(|readback| !readback.map_requested) {
+            let state = readback.state.clone();
+            readback.buffer.slice(..).map_async(MapMode::Read, move |result| {
+                state.store(if result.is_ok() { MAP_DONE } else { MAP_FAILED }, Ordering::Release);
+            });
+            readback.map_requested = true;
+        }
+    }
+
+    /// Returns the captures whose readback has completed, as RGBA8 pixels. Does not wait for
+    /// the others; the device must be polled for them to progress.
+    pub(super) fn take_completed(&mut self) -> Vec<FrameCapture> {
+        let mut captures = vec![];
+
+        self.readbacks.retain(|readback| match readback.state.load(Ordering::Acquire) {
+            MAP_PENDING => true,
+            MAP_DONE => {
+                captures.push(Self::read(readback));
+                readback.buffer.unmap();
+                false
+            },
+            _ => {
+                log::error!("Failed to map the window capture readback buffer");
+                false
+            },
+        });
+
+        captures
+    }
+
+    // Copies the mapped readback buffer, removing the row padding and converting BGRA to RGBA.
+    #[allow(clippy::as_conversions, clippy::arithmetic_side_effects)]
+    fn read(readback: &Readback) -> FrameCapture {
+        let view = readback.buffer.slice(..).get_mapped_range();
+        let row_size = readback.width as usize * 4;
+        let bgra =
+            matches!(readback.format, TextureFormat::Bgra8Unorm | TextureFormat::Bgra8UnormSrgb);
+
+        let mut color = Vec::with_capacity(row_size * readback.height as usize);
+        for row in view.chunks_exact(readback.bytes_per_row as usize) {
+            color.extend_from_slice(&row[..row_size]);
+        }
+        if bgra {
+            for pixel in color.chunks_exact_mut(4) {
+                pixel.swap(0, 2);
+            }
+        }
+
+        FrameCapture { width: readback.width, height: readback.height, color, depth: vec![] }
+    }
+}
@@ -0,0 +1,186 @@
+use std::fs;
+use std::io::ErrorKind;
+use std::path::Path;
+
+use anyhow::{Context, Result, anyhow, ensure};
+use clap::ValueEnum;
+use egui_sdl2_platform::sdl2;
+use sdl2::VideoSubsystem;
+use sdl2::video::{DisplayMode, FullscreenType, Window, WindowBuilder};
+
+use super::sdl_wgpu::SdlWgpuConfiguration;
+
+/// How the window covers the display.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, ValueEnum)]
+pub(crate) enum WindowMode {
+    Windowed,
+    /// Exclusive fullscreen, switching the display to the chosen display mode.
+    Fullscreen,
+    /// Borderless window covering the display at its desktop resolution.
+    Desktop,
+}
+
+impl WindowMode {
+    pub(super) const ALL: [Self; 3] = [Self::Windowed, Self::Fullscreen, Self::Desktop];
+
+    pub(super) const fn label(self) -> &'static str {
+        match self {
+            Self::Windowed => "Windowed",
+            Self::Fullscreen => "Exclusive fullscreen",
+            Self::Desktop => "Desktop fullscreen",
+        }
+    }
+
+    const fn fullscreen_type(self) -> FullscreenType {
+        match self {
+            Self::Windowed => FullscreenType::Off,
+            Self::Fullscreen => FullscreenType::True,
+            Self::Desktop => FullscreenType::Desktop,
+        }
+    }
+
+    /// Returns the mode the window is in.
+    pub(super) fn of(window: &Window) -> Self {
+        match window.fullscreen_state() {
+            FullscreenType::Off => Self::Windowed,
+            FullscreenType::True => Self::Fullscreen,
+            FullscreenType::Desktop => Self::Desktop,
+        }
+    }
+
+    /// Switches `window` to this mode. In exclusive fullscreen, the display switches to
+    /// `display_mode`, or keeps its desktop mode when `None`.
+    pub(super) fn apply(
+        self,
+        window: &mut Window,
+        display_mode: Option<DisplayMode>,
+    ) -> Result<()> {
+        if self == Self::Fullscreen {
+            window
+                .set_display_mode(display_mode)
+                .map_err(|e| anyhow!("Failed to set the display mode: {}", e))?;
+        }
+        window
+            .set_fullscreen(self.fullscreen_type())
+            .map_err(|e| anyhow!("Failed to switch to {}: {}", self.label(), e))
+    }
+}
+
+/// Position and size of the window in windowed mode, in screen coordinates, kept across runs.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub(super) struct WindowGeometry {
+    pub x:      i32,
+    pub y:      i32,
+    pub width:  u32,
+    pub height: u32,
+}
+
+impl WindowGeometry {
+    /// Returns the current geometry of `window`.
+    pub(super) fn of(window: &Window) -> Self {
+        let ((x, y), (width, height)) = (window.position(), window.size());
+        Self { x, y, width, height }
+    }
+
+    /// Loads the geometry saved by a previous run, as a single `x y width height` line, or
+    /// `None` when there is none.
+    pub(super) fn load(path: &Path) -> Result<Option<Self>> {
+        let text = match fs::read_to_string(path) {
+            Ok(text) => text,
+            Err(err) if err.kind() == ErrorKind::NotFound => return Ok(None),
+            Err(err) =>
+                return Err(err)
+                    .with_context(|| format!("Failed to read window state {}", path.display())),
+        };
+
+        let values = text
+            .split_whitespace()
+            .map(str::parse::<i64>)
+            .collect::<Result<Vec<_>, _>>()
+            .with_context(|| format!("Invalid window state {}", path.display()))?;
+        ensure!(
+            values.len() == 4,
+            "Invalid window state {}: expected 4 values, found {}",
+            path.display(),
+            values.len()
+        );
+
+        let geometry = Self {
+            x:      i32::try_from(values[0])?,
+            y:      i32::try_from(values[1])?,
+            width:  u32::try_from(values[2])?,
+            height: u32::try_from(values[3])?,
+        };
+        ensure!(geometry.width > 0 && geometry.height > 0, "Empty window in {}", path.display());
+        Ok(Some(geometry))
+    }
+
+    pub(super) fn save(&self, path: &Path) -> Result<()> {
+        let Self { x, y, width, height } = self;
+        fs::write(path, format!("{x} {y} {width} {height}\n"))
+            .with_context(|| format!("Failed to write window state {}", path.display()))
+    }
+}
+
+/// Returns a builder of the window of `cfg`, at its saved position or centered on its display.
+/// The window mode is applied once the window is built.
+pub(super) fn window_builder(video: &VideoSubsystem, cfg: &SdlWgpuConfiguration) -> WindowBuilder {
+    let mut window_builder = video.window(cfg.title, cfg.width, cfg.height);
+    window_builder.allow_highdpi();
+
+    match cfg.position {
+        Some((x, y)) => window_builder.position(x, y),
+        None => match centered_position(video, cfg.display, cfg.width, cfg.height) {
+            Ok((x, y)) => window_builder.position(x, y),
+            Err(err) => {
+                log::warn!("{err:#}, centering the window on the primary display");
+                window_builder.position_centered()
+            },
+        },
+    };
+
+    window_builder
+}
+
+/// Returns the position of a window of `width` x `height` centered on `display`.
+#[allow(clippy::arithmetic_side_effects)]
+pub(super) fn centered_position(
+    video: &VideoSubsystem,
+    display: i32,
+    width: u32,
+    height: u32,
+) -> Result<(i32, i32)> {
+    let bounds = video
+        .display_bounds(display)
+        .map_err(|e| anyhow!("Failed to get the bounds of display {}: {}", display, e))?;
+    let center = |start: i32, extent: u32, size: u32| {
+        let offset = (i64::from(extent) - i64::from(size)) / 2;
+        i32::try_from(i64::from(start) + offset.max(0)).unwrap_or(start)
+    };
+    Ok((center(bounds.x(), bounds.width(), width), center(bounds.y(), bounds.height(), height)))
+}
+
+/// Returns the names of the connected displays, by index.
+pub(super) fn display_names(video: &VideoSubsystem) -> Vec<String> {
+    let count = video.num_video_displays().unwrap_or_default();
+    (0..count)
+        .map(|display| video.display_name(display).unwrap_or_else(|_| format!("Display {display}")))
+        .collect()
+}
+
+/// Returns the fullscreen display modes of `display`, from the largest.
+pub(super) fn display_modes(video: &VideoSubsystem, display: i32) -> Vec<DisplayMode> {
+    let count = video.num_display_modes(display).unwrap_or_default();
+    let mut modes: Vec<DisplayMode> =
+        (0..count).filter_map(|mode| video.display_mode(display, mode).ok()).collect();
+    // The modes only differing by their pixel format look the same.
+    modes.dedup_by_key(|mode| (mode.w, mode.h, mode.refresh_rate));
+    modes
+}
+
+pub(super) fn display_mode_label(display_mode: Option<DisplayMode>) -> String {
+    match display_mode {
+        Some(DisplayMode { w, h, refresh_rate, .. }) => format!("{w}x{h} @ {refresh_rate} Hz"),
+        None => "Desktop resolution".to_owned(),
+    }
+}
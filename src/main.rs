@@ -3,58 +3,389 @@
 //! ...
 #![allow(unused_results)]
 
-use anyhow::Result;
-use clap::Parser;
+use std::path::PathBuf;
+
+use anyhow::{Result, anyhow};
+use clap::{ArgAction, Args, Parser, Subcommand};
 use dotenv::dotenv;
 #[cfg(target_os = "linux")]
 use tikv_jemallocator::Jemalloc;
 
 mod app;
-use app::{App, AppConfiguration, constants, log_utils};
-use constants::{HEIGHT, TARGET_FPS, TITLE, WIDTH};
+use app::{
+    AdapterPreference,
+    App,
+    AppConfiguration,
+    AppOptionsBuilder,
+    DepthFormat,
+    GraphicsBackend,
+    HeadlessConfiguration,
+    HeadlessOptionsBuilder,
+    ImageFileFormat,
+    PacingMode,
+    Presenter,
+    RecordingFormat,
+    ScaleMode,
+    TerminalMode,
+    TimestepMode,
+    WindowMode,
+    constants,
+    headless,
+    log_utils,
+};
+use constants::{
+    HEADLESS_FRAMES,
+    HEIGHT,
+    RECORDING_DIR,
+    RENDER_BUFFERS,
+    RENDER_DIR,
+    RENDER_SCALE,
+    SCREENSHOT_DIR,
+    TARGET_FPS,
+    TITLE,
+    WIDTH,
+    WINDOW_STATE,
+};
 
 #[cfg(target_os = "linux")]
 #[global_allocator]
 static GLOBAL: Jemalloc = Jemalloc;
 
 #[derive(Debug, Parser)]
+#[allow(clippy::struct_excessive_bools)]
 struct Cli {
-    #[arg(long = "width", default_value_t = WIDTH)]
-    /// Window width
-    width: u32,
+    #[command(subcommand)]
+    command: Option<Command>,
+
+    #[arg(long = "presenter", value_enum, default_value_t = Presenter::Wgpu)]
+    /// How the frames are presented; `sdl` runs without wgpu and without the GUI
+    presenter: Presenter,
+
+    #[arg(long = "width")]
+    /// Window width (defaults to the width of the last run, or 1920)
+    width: Option<u32>,
+
+    #[arg(long = "height")]
+    /// Window height (defaults to the height of the last run, or 1080)
+    height: Option<u32>,
+
+    #[arg(long = "render_width", value_parser = clap::value_parser!(u32).range(1..))]
+    /// Internal render width (defaults to the window width scaled by `render_scale`)
+    render_width: Option<u32>,
+
+    #[arg(long = "render_height", value_parser = clap::value_parser!(u32).range(1..))]
+    /// Internal render height (defaults to the window height scaled by `render_scale`)
+    render_height: Option<u32>,
+
+    #[arg(long = "render_scale", default_value_t = RENDER_SCALE, value_parser = parse_render_scale)]
+    /// Internal render resolution relative to the window size
+    render_scale: f32,
+
+    #[arg(long = "scale_mode", value_enum, default_value_t = ScaleMode::Fit)]
+    /// How the internal render resolution is scaled to the window
+    scale_mode: ScaleMode,
 
-    #[arg(long = "height", default_value_t = HEIGHT)]
-    /// Window height
-    height: u32,
+    #[arg(long = "track_window_size", default_value_t = false)]
+    /// Resize the internal render resolution along with the window
+    track_window_size: bool,
+
+    #[arg(long = "window_mode", value_enum, default_value_t = WindowMode::Windowed)]
+    /// How the window covers the display
+    window_mode: WindowMode,
 
     #[arg(long = "fullscreen", default_value_t = false)]
-    /// Enable fullscreen mode
+    /// Enable fullscreen mode, same as `--window_mode fullscreen`
     fullscreen: bool,
 
-    #[arg(long = "vsync", default_value_t = true)]
-    /// Enable vsync
+    #[arg(long = "display")]
+    /// Index of the display showing the window, centered on it (defaults to the position of
+    /// the last run)
+    display: Option<i32>,
+
+    #[arg(long = "window_state", default_value = WINDOW_STATE)]
+    /// File keeping the windowed position and size of the window across runs
+    window_state: PathBuf,
+
+    #[arg(long = "vsync", default_value_t = true, action = ArgAction::Set, num_args = 0..=1,
+          default_missing_value = "true")]
+    /// Enable vsync (`--vsync false` to disable it)
     vsync: bool,
 
-    #[arg(long = "target_fps", default_value_t = TARGET_FPS)]
+    #[arg(long = "backend", value_enum, default_value_t = GraphicsBackend::Auto)]
+    /// Graphics API used by wgpu
+    backend: GraphicsBackend,
+
+    #[arg(long = "power_preference", value_enum, default_value_t = AdapterPreference::Default)]
+    /// Which adapter is preferred when several are available
+    power_preference: AdapterPreference,
+
+    #[arg(long = "force_fallback_adapter", default_value_t = false)]
+    /// Only use a fallback adapter, usually a software rasterizer
+    force_fallback_adapter: bool,
+
+    #[arg(long = "list_adapters", default_value_t = false)]
+    /// Print the adapters available for `backend` with their surface formats, then exit
+    list_adapters: bool,
+
+    #[arg(long = "target_fps", default_value_t = TARGET_FPS,
+          value_parser = clap::value_parser!(u32).range(1..))]
     /// Target frames per second
-    target_fps: i32,
+    target_fps: u32,
+
+    #[arg(long = "pacing", value_enum, conflicts_with = "virtual_clock")]
+    /// How the frames are paced (defaults to `vsync` with vsync, `hybrid` without, and
+    /// `fixed-fps` with `virtual_clock`)
+    pacing: Option<PacingMode>,
+
+    #[arg(long = "timestep", value_enum, default_value_t = TimestepMode::Fixed)]
+    /// How the simulation time advances between frames
+    timestep: TimestepMode,
+
+    #[arg(long = "dynamic_resolution", default_value_t = false)]
+    /// Scale the render resolution to keep the frame time within the target fps budget
+    dynamic_resolution: bool,
+
+    #[arg(long = "render_thread", default_value_t = false)]
+    /// Draw the frames on a dedicated render thread (ignored headless, where frames are drawn
+    /// synchronously)
+    render_thread: bool,
+
+    #[arg(long = "render_buffers", default_value_t = RENDER_BUFFERS,
+          value_parser = clap::value_parser!(u8).range(2..=3))]
+    /// Number of frame buffers used with the render thread (2 or 3)
+    render_buffers: u8,
+
+    #[arg(long = "pipelined", default_value_t = false, requires = "render_thread")]
+    /// With the render thread, overlap the world update of the next frame with the rendering of
+    /// the current one, presenting each frame once complete
+    pipelined: bool,
+
+    #[arg(long = "virtual_clock", default_value_t = false)]
+    /// Drive the main loop from a simulated clock advancing by exactly one frame at `target_fps`
+    /// per frame, for deterministic runs
+    virtual_clock: bool,
+
+    #[arg(long = "screenshot_dir", default_value = SCREENSHOT_DIR)]
+    /// Directory receiving the screenshots
+    screenshot_dir: PathBuf,
+
+    #[arg(long = "screenshot_format", value_enum, default_value_t = ImageFileFormat::Png)]
+    /// File format of the screenshots
+    screenshot_format: ImageFileFormat,
+
+    #[arg(long = "screenshot_depth", value_enum)]
+    /// Also dump the depth buffer with each screenshot
+    screenshot_depth: Option<DepthFormat>,
+
+    #[arg(long, default_value_t = false)]
+    /// Start recording the rendered frames at launch (toggled with F9)
+    record: bool,
+
+    #[arg(long = "recording_dir", default_value = RECORDING_DIR)]
+    /// Directory receiving the recordings
+    recording_dir: PathBuf,
+
+    #[arg(long = "recording_format", value_enum, default_value_t = RecordingFormat::Images)]
+    /// Container of the recordings; image sequences use the screenshot format
+    recording_format: RecordingFormat,
+
+    #[arg(long = "recording_fixed_timestep", default_value_t = false)]
+    /// Advance the simulation by exactly one frame at `target_fps` per recorded frame, so that
+    /// recordings play back at the right speed even when rendering is slower than real time
+    recording_fixed_timestep: bool,
+
+    #[arg(long, default_value_t = false)]
+    /// Run the engine without a window or a GPU, rendering into the frame buffer only, for a
+    /// fixed number of frames at a fixed timestep of one frame at `target_fps`
+    headless: bool,
+
+    #[arg(long = "frames", default_value_t = HEADLESS_FRAMES)]
+    /// Number of frames rendered in headless mode
+    frames: u32,
+
+    #[arg(long = "output_dir")]
+    /// Directory receiving the last frames rendered in headless mode, in the screenshot format
+    output_dir: Option<PathBuf>,
+
+    #[arg(long = "output_frames", default_value_t = 1)]
+    /// Number of final frames written to `output_dir` in headless mode
+    output_frames: u32,
+
+    #[arg(long = "terminal", value_enum)]
+    /// Draw the frames rendered in headless mode in the terminal, refreshed in place
+    terminal: Option<TerminalMode>,
+
+    #[arg(long = "terminal_columns")]
+    /// Width of the terminal drawing in characters (defaults to `$COLUMNS`, or 80)
+    terminal_columns: Option<u32>,
+
+    #[arg(long = "terminal_rows")]
+    /// Height of the terminal drawing in characters (defaults to `$LINES` - 1, or 23)
+    terminal_rows: Option<u32>,
+
+    #[arg(long = "gui_overlay", default_value_t = false)]
+    /// Draw a GUI window over the frames rendered in headless mode, rasterized in software
+    gui_overlay: bool,
+
+    #[arg(long = "golden")]
+    /// Reference image compared with the last frame rendered in headless mode; the run fails on
+    /// a mismatch and writes the actual frame and a diff image next to the reference
+    golden: Option<PathBuf>,
+
+    #[arg(long = "golden_tolerance", default_value_t = 2)]
+    /// Largest difference of a color channel for which a pixel matches the reference
+    golden_tolerance: u8,
+
+    #[arg(long = "golden_max_pixels", default_value_t = 0)]
+    /// Number of pixels allowed to differ from the reference
+    golden_max_pixels: u64,
+
+    #[arg(long = "update_golden", default_value_t = false, requires = "golden")]
+    /// Write the last frame as the new reference image instead of comparing it
+    update_golden: bool,
 }
 
-impl From<Cli> for AppConfiguration {
-    fn from(cli: Cli) -> Self {
-        AppConfiguration::new(
-            TITLE,
-            cli.width,
-            cli.height,
-            cli.fullscreen,
-            cli.vsync,
-            cli.target_fps,
-        )
+impl TryFrom<Cli> for AppConfiguration {
+    type Error = anyhow::Error;
+
+    fn try_from(cli: Cli) -> Result<Self> {
+        let options = AppOptionsBuilder::default()
+            .title(TITLE)
+            .presenter(cli.presenter)
+            .width(cli.width)
+            .height(cli.height)
+            .render_width(cli.render_width)
+            .render_height(cli.render_height)
+            .render_scale(cli.render_scale)
+            .scale_mode(cli.scale_mode)
+            .track_window_size(cli.track_window_size)
+            .window_mode(if cli.fullscreen { WindowMode::Fullscreen } else { cli.window_mode })
+            .display(cli.display)
+            .window_state(cli.window_state)
+            .vsync(cli.vsync)
+            .backend(cli.backend)
+            .adapter_preference(cli.power_preference)
+            .force_fallback_adapter(cli.force_fallback_adapter)
+            .target_fps(cli.target_fps)
+            .pacing_mode(cli.pacing)
+            .timestep_mode(cli.timestep)
+            .dynamic_resolution(cli.dynamic_resolution)
+            .render_thread(cli.render_thread)
+            .render_buffers(usize::from(cli.render_buffers))
+            .pipelined(cli.pipelined)
+            .virtual_clock(cli.virtual_clock)
+            .screenshot_dir(cli.screenshot_dir)
+            .screenshot_format(cli.screenshot_format)
+            .screenshot_depth(cli.screenshot_depth)
+            .record(cli.record)
+            .recording_dir(cli.recording_dir)
+            .recording_format(cli.recording_format)
+            .recording_fixed_timestep(cli.recording_fixed_timestep)
+            .build()
+            .map_err(|err| anyhow!("Failed to build the application options: {err}"))?;
+
+        Ok(AppConfiguration::new(options))
+    }
+}
+
+#[derive(Debug, Subcommand)]
+enum Command {
+    /// Render a sequence of frames of a scene offline, without a window or a GPU, following a
+    /// scripted camera path at a fixed time step, write each frame to disk, then exit
+    Render(RenderArgs),
+}
+
+#[derive(Debug, Args)]
+struct RenderArgs {
+    #[arg(long = "scene")]
+    /// Scene file, with one `background`, `triangle` or `quad` element per line (defaults to the
+    /// demo scene)
+    scene: Option<PathBuf>,
+
+    #[arg(long = "camera_path")]
+    /// Camera path file, with one `time x y z yaw pitch` keyframe per line
+    camera_path: Option<PathBuf>,
+
+    #[arg(long = "frames", default_value_t = HEADLESS_FRAMES)]
+    /// Number of frames to render
+    frames: u32,
+
+    #[arg(long = "dt")]
+    /// Simulated time between two frames in seconds (defaults to one frame at `target_fps`)
+    dt: Option<f32>,
+
+    #[arg(long = "output_dir", default_value = RENDER_DIR)]
+    /// Directory receiving the frames, in the screenshot format
+    output_dir: PathBuf,
+}
+
+impl TryFrom<Cli> for HeadlessConfiguration {
+    type Error = anyhow::Error;
+
+    fn try_from(cli: Cli) -> Result<Self> {
+        // The render subcommand writes every frame.
+        let (frames, output_dir, output_frames, dt, scene, camera_path) = match cli.command {
+            Some(Command::Render(render)) => (
+                render.frames,
+                Some(render.output_dir),
+                render.frames,
+                render.dt,
+                render.scene,
+                render.camera_path,
+            ),
+            None => (cli.frames, cli.output_dir, cli.output_frames, None, None, None),
+        };
+
+        let options = HeadlessOptionsBuilder::default()
+            .width(cli.width.unwrap_or(WIDTH))
+            .height(cli.height.unwrap_or(HEIGHT))
+            .render_width(cli.render_width)
+            .render_height(cli.render_height)
+            .render_scale(cli.render_scale)
+            .target_fps(cli.target_fps)
+            .dt(dt)
+            .scene(scene)
+            .camera_path(camera_path)
+            .render_thread(cli.render_thread)
+            .frames(frames)
+            .output_dir(output_dir)
+            .output_frames(output_frames)
+            .output_format(cli.screenshot_format)
+            .terminal(cli.terminal)
+            .terminal_columns(cli.terminal_columns)
+            .terminal_rows(cli.terminal_rows)
+            .gui_overlay(cli.gui_overlay)
+            .golden_reference(cli.golden)
+            .golden_tolerance(cli.golden_tolerance)
+            .golden_max_differing_pixels(cli.golden_max_pixels)
+            .update_golden(cli.update_golden)
+            .build()
+            .map_err(|err| anyhow!("Failed to build the headless options: {err}"))?;
+
+        Ok(HeadlessConfiguration::new(options))
+    }
+}
+
+/// Parses a render scale, which must be a finite positive number.
+fn parse_render_scale(value: &str) -> Result<f32, String> {
+    let scale = value.parse::<f32>().map_err(|err| err.to_string())?;
+    if scale.is_finite() && scale > 0.0 {
+        Ok(scale)
+    } else {
+        Err(format!("{scale} is not a finite positive number"))
     }
 }
 
 fn main() -> Result<()> {
     dotenv().ok();
     log_utils::init_logging()?;
-    App::start(Cli::parse().into())
+
+    let cli = Cli::parse();
+    if cli.list_adapters {
+        App::list_adapters(&cli.try_into()?)
+    } else if cli.headless || cli.command.is_some() {
+        headless::start(&cli.try_into()?)
+    } else {
+        App::start(cli.try_into()?)
+    }
 }